@@ -0,0 +1,268 @@
+//! Sites stored in a `.tar`, `.tar.gz`, `.tgz` or `.zip` archive, or EPUB publications, which are
+//! checked without extracting them. Files in an archive are named like `site.tar.gz!/path/in/archive.html`.
+
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Error};
+use flate2::read::GzDecoder;
+
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+/// Separates the path of an archive from the path of a file in it.
+const SEPARATOR: &str = "!";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ArchiveKind {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl ArchiveKind {
+    fn from_path(path: &Path) -> Option<ArchiveKind> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if name.ends_with(".zip") || name.ends_with(".epub") {
+            Some(ArchiveKind::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct Archive {
+    path: PathBuf,
+    kind: ArchiveKind,
+    /// The folder in the archive that is checked, such as `public` for `site.zip!/public`.
+    prefix: PathBuf,
+}
+
+impl Archive {
+    /// Recognize a base path that is an archive, or a folder in an archive such as
+    /// `site.zip!/public`.
+    pub fn from_base_path(base_path: &Path) -> Option<Archive> {
+        let split = base_path
+            .to_str()
+            .and_then(|base_path| base_path.split_once(SEPARATOR));
+
+        let (path, prefix) = match split {
+            Some((path, prefix)) if !base_path.is_file() => {
+                (Path::new(path), prefix.trim_matches('/'))
+            }
+            _ => (base_path, ""),
+        };
+
+        let kind = ArchiveKind::from_path(path)?;
+
+        if !path.is_file() {
+            return None;
+        }
+
+        Some(Archive {
+            path: path.to_owned(),
+            kind,
+            prefix: PathBuf::from(prefix),
+        })
+    }
+
+    /// Whether this is an EPUB publication that is checked as a whole, see `--epub`.
+    pub fn is_epub(&self) -> bool {
+        let is_epub = matches!(
+            self.path.extension().and_then(|extension| extension.to_str()),
+            Some(x) if x.eq_ignore_ascii_case("epub")
+        );
+        is_epub && self.prefix.as_os_str().is_empty()
+    }
+
+    /// The path of the archive file itself.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The path that files in the archive are named relative to, see `Document::with_profile`.
+    pub fn base_path(&self) -> PathBuf {
+        self.member_path(&self.prefix)
+    }
+
+    /// Call `f` with the path and contents of every regular file in the checked folder of the
+    /// archive, in the order they are stored.
+    pub fn for_each_file<F>(&self, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(PathBuf, &mut dyn Read) -> Result<(), Error>,
+    {
+        let file = BufReader::new(
+            File::open(&self.path)
+                .with_context(|| format!("Failed to open archive {}", self.path.display()))?,
+        );
+
+        match self.kind {
+            ArchiveKind::Tar => self.for_each_tar_file(file, f),
+            ArchiveKind::TarGz => self.for_each_tar_file(GzDecoder::new(file), f),
+            ArchiveKind::Zip => {
+                let mut archive = zip::ZipArchive::new(file)?;
+
+                for i in 0..archive.len() {
+                    let mut entry = archive.by_index(i)?;
+                    if !entry.is_file() {
+                        continue;
+                    }
+
+                    if let Some(path) = entry.enclosed_name().and_then(|name| self.file_path(name))
+                    {
+                        f(path, &mut entry)?;
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// The paths of all files that `for_each_file` is called with. This reads the archive once
+    /// more, as it can only be read from start to end.
+    pub fn file_paths(&self) -> Result<BTreeSet<PathBuf>, Error> {
+        let mut paths = BTreeSet::new();
+        self.for_each_file(|path, _| {
+            paths.insert(path);
+            Ok(())
+        })?;
+        Ok(paths)
+    }
+
+    fn for_each_tar_file<R, F>(&self, read: R, mut f: F) -> Result<(), Error>
+    where
+        R: Read,
+        F: FnMut(PathBuf, &mut dyn Read) -> Result<(), Error>,
+    {
+        let mut archive = tar::Archive::new(read);
+
+        for entry in archive
+            .entries()
+            .with_context(|| format!("Failed to read archive {}", self.path.display()))?
+        {
+            let mut entry =
+                entry.with_context(|| format!("Failed to read archive {}", self.path.display()))?;
+
+            // Like for folders, symlinks are not followed.
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+
+            if let Some(path) = self.file_path(&entry.path()?) {
+                f(path, &mut entry)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The path of a file in the archive, such as `site.zip!/public/index.html`, or `None` if the
+    /// file is not in the checked folder.
+    fn file_path(&self, name: &Path) -> Option<PathBuf> {
+        // Tarballs created with `tar -C public .` name their files `./index.html`.
+        let name: PathBuf = name
+            .components()
+            .filter(|component| !matches!(component, Component::CurDir | Component::RootDir))
+            .collect();
+
+        if !name.starts_with(&self.prefix) || name.components().any(|c| c == Component::ParentDir) {
+            return None;
+        }
+
+        Some(self.member_path(&name))
+    }
+
+    /// Name a path in the archive like `site.zip!/public/index.html`. Its components are always
+    /// separated by `/`, as in the archive itself, also on Windows.
+    fn member_path(&self, name: &Path) -> PathBuf {
+        let mut path = OsString::from(self.path.as_os_str());
+        path.push(SEPARATOR);
+        for component in name.components() {
+            path.push("/");
+            path.push(component);
+        }
+        PathBuf::from(path)
+    }
+}
+
+#[test]
+fn test_file_path() {
+    let archive = Archive {
+        path: PathBuf::from("build/site.tar.gz"),
+        kind: ArchiveKind::TarGz,
+        prefix: PathBuf::from("public"),
+    };
+
+    assert_eq!(
+        archive.base_path().to_str(),
+        Some("build/site.tar.gz!/public")
+    );
+    assert_eq!(
+        archive
+            .file_path(Path::new("./public/guide/index.html"))
+            .unwrap()
+            .to_str(),
+        Some("build/site.tar.gz!/public/guide/index.html")
+    );
+    assert_eq!(archive.file_path(Path::new("src/index.md")), None);
+    assert_eq!(archive.file_path(Path::new("public/../secret.html")), None);
+
+    assert_eq!(
+        Path::new("build/site.tar.gz!/public/index.html").strip_prefix(archive.base_path()),
+        Ok(Path::new("index.html"))
+    );
+}
+
+#[test]
+fn test_epub_file_path() {
+    let archive = Archive {
+        path: PathBuf::from("book.epub"),
+        kind: ArchiveKind::Zip,
+        prefix: PathBuf::new(),
+    };
+
+    assert!(archive.is_epub());
+    assert_eq!(archive.base_path().to_str(), Some("book.epub!"));
+    assert_eq!(
+        archive
+            .file_path(Path::new("OEBPS/text/ch1.xhtml"))
+            .unwrap()
+            .to_str(),
+        Some("book.epub!/OEBPS/text/ch1.xhtml")
+    );
+    assert_eq!(
+        Path::new("book.epub!/OEBPS/text/ch1.xhtml").strip_prefix(archive.base_path()),
+        Ok(Path::new("OEBPS/text/ch1.xhtml"))
+    );
+}
+
+#[test]
+fn test_archive_kind() {
+    assert_eq!(
+        ArchiveKind::from_path(Path::new("site.TGZ")),
+        Some(ArchiveKind::TarGz)
+    );
+    assert_eq!(
+        ArchiveKind::from_path(Path::new("site.tar")),
+        Some(ArchiveKind::Tar)
+    );
+    assert_eq!(
+        ArchiveKind::from_path(Path::new("site.zip")),
+        Some(ArchiveKind::Zip)
+    );
+    assert_eq!(
+        ArchiveKind::from_path(Path::new("handbook.epub")),
+        Some(ArchiveKind::Zip)
+    );
+    assert_eq!(ArchiveKind::from_path(Path::new("public")), None);
+}
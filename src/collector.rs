@@ -1,9 +1,15 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::PathBuf;
 use std::sync::Arc;
 
 use patricia_tree::PatriciaMap;
+use regex::Regex;
 
-use crate::html::{Href, Link, UsedLink};
+use crate::html::{
+    directives_match, is_checked_pdf_parameter, pattern_matches, split_directives, Diagnostic,
+    DiagnosticKind, Href, Link, RelationKind, ServerProfile, UnicodeForm, UsedLink,
+};
 
 impl<'a> AsRef<[u8]> for Href<'a> {
     fn as_ref(&self) -> &[u8] {
@@ -72,15 +78,38 @@ impl<P: Copy> LinkState<P> {
             LinkState::Defined => (),
             LinkState::Undefined(links) => match other {
                 LinkState::Defined => *self = LinkState::Defined,
-                LinkState::Undefined(links2) => links.extend(links2.into_iter()),
+                LinkState::Undefined(links2) => links.extend(links2),
             },
         }
     }
 }
 
+#[derive(Debug)]
+struct OwnedRedirect {
+    destination: String,
+    path: Arc<PathBuf>,
+}
+
+#[derive(Debug)]
+struct OwnedRelation {
+    kind: RelationKind,
+    source: String,
+    target: String,
+    hreflang: Option<String>,
+    path: Arc<PathBuf>,
+}
+
 /// Link collector used for actual link checking. Keeps track of broken links only.
 pub struct BrokenLinkCollector<P> {
     links: PatriciaMap<LinkState<P>>,
+    /// Redirect source patterns, every href matching one of them is defined.
+    patterns: Vec<String>,
+    /// Redirects by source href.
+    redirects: BTreeMap<String, OwnedRedirect>,
+    /// Hrefs of all HTML documents.
+    pages: Vec<String>,
+    relations: Vec<OwnedRelation>,
+    diagnostics: Vec<Diagnostic>,
     used_link_count: usize,
 }
 
@@ -88,6 +117,11 @@ impl<P: Send + Copy> LinkCollector<P> for BrokenLinkCollector<P> {
     fn new() -> Self {
         BrokenLinkCollector {
             links: PatriciaMap::new(),
+            patterns: Vec::new(),
+            redirects: BTreeMap::new(),
+            pages: Vec::new(),
+            relations: Vec::new(),
+            diagnostics: Vec::new(),
             used_link_count: 0,
         }
     }
@@ -107,11 +141,40 @@ impl<P: Send + Copy> LinkCollector<P> for BrokenLinkCollector<P> {
             Link::Defines(defined_link) => {
                 self.links.insert(defined_link.href, LinkState::Defined);
             }
+            Link::DefinesPage(defined_link) => {
+                self.pages.push(defined_link.href.0.to_owned());
+                self.links.insert(defined_link.href, LinkState::Defined);
+            }
+            Link::DefinesPattern(defined_link) => {
+                self.patterns.push(defined_link.href.0.to_owned());
+            }
+            Link::Redirects(redirect) => {
+                self.redirects.insert(
+                    redirect.source.0.to_owned(),
+                    OwnedRedirect {
+                        destination: redirect.destination.0.to_owned(),
+                        path: redirect.path,
+                    },
+                );
+            }
+            Link::Relates(relation) => self.relations.push(OwnedRelation {
+                kind: relation.kind,
+                source: relation.source.0.to_owned(),
+                target: relation.target.0.to_owned(),
+                hreflang: relation.hreflang.map(str::to_owned),
+                path: relation.path,
+            }),
+            Link::Reports(diagnostic) => self.diagnostics.push(diagnostic),
         }
     }
 
     fn merge(&mut self, other: Self) {
         self.used_link_count += other.used_link_count;
+        self.patterns.extend(other.patterns);
+        self.redirects.extend(other.redirects);
+        self.pages.extend(other.pages);
+        self.relations.extend(other.relations);
+        self.diagnostics.extend(other.diagnostics);
 
         for (href, other_state) in other.links {
             if let Some(state) = self.links.get_mut(&href) {
@@ -123,26 +186,446 @@ impl<P: Send + Copy> LinkCollector<P> for BrokenLinkCollector<P> {
     }
 }
 
+/// Fragments that browsers scroll to even without a matching element: the empty fragment and
+/// `#top`, see https://html.spec.whatwg.org/multipage/browsing-the-web.html#find-a-potential-indicated-element
+fn is_implicit_anchor(anchor: &str) -> bool {
+    let anchor = anchor.trim_start_matches('#');
+    anchor.is_empty() || anchor.eq_ignore_ascii_case("top")
+}
+
+/// What is needed to check anchors besides the collected links.
+#[derive(Default)]
+pub struct AnchorChecks {
+    /// The normalized visible text of pages by href, see `text_fragment_pages`.
+    pub page_texts: BTreeMap<String, String>,
+    /// Anchors (without `#`) that are valid on every page if they match one of these, see
+    /// `--valid-anchor`.
+    pub valid_anchors: Vec<Regex>,
+}
+
+impl AnchorChecks {
+    fn is_always_valid(&self, anchor: &str) -> bool {
+        is_implicit_anchor(anchor)
+            || self
+                .valid_anchors
+                .iter()
+                .any(|pattern| pattern.is_match(anchor.trim_start_matches('#')))
+    }
+}
+
+/// Find the defined href that the server would serve for `href` if differences removed by `fold`
+/// are ignored, see `BrokenLinkCollector::folded_index`.
+fn resolve_folded(
+    folded: &BTreeMap<String, String>,
+    href: &str,
+    profile: ServerProfile,
+    fold: impl Fn(&str) -> String,
+) -> Option<String> {
+    let href = fold(href);
+    let resolved = profile.resolve(&href, |x| folded.contains_key(x))?;
+    folded.get(&*resolved).cloned()
+}
+
+fn fold_case(href: &str) -> String {
+    href.to_lowercase()
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub struct BrokenLink<P> {
     pub hard_404: bool,
     pub link: OwnedUsedLink<P>,
+    /// The defined href that matches the link if case is ignored.
+    pub case_mismatch: Option<String>,
 }
 
 impl<P: Copy + PartialEq> BrokenLinkCollector<P> {
-    pub fn get_broken_links(&self, check_anchors: bool) -> impl Iterator<Item = BrokenLink<P>> {
+    fn is_defined(&self, href: &str) -> bool {
+        self.is_defined_exactly(href)
+            || self
+                .patterns
+                .iter()
+                .any(|pattern| pattern_matches(pattern, href))
+    }
+
+    fn is_defined_exactly(&self, href: &str) -> bool {
+        matches!(self.links.get(href), Some(&LinkState::Defined))
+    }
+
+    /// The page and anchor that `resolved` and `anchor` end up at after following redirects.
+    /// Returns `None` if the redirects don't lead to a page.
+    fn anchor_target(
+        &self,
+        resolved: &str,
+        anchor: &str,
+        profile: ServerProfile,
+    ) -> Option<(String, String)> {
+        let (hops, is_loop) = self.follow_redirects(&format!("{}{}", resolved, anchor), profile);
+
+        match hops.last() {
+            // Loops are reported separately.
+            Some(_) if is_loop => None,
+            Some(destination) => {
+                let without_anchor = Href(destination).without_anchor().0;
+                // A broken destination is reported where the redirect is defined.
+                let resolved = profile.resolve(without_anchor, |x| self.is_defined(x))?;
+                Some((
+                    resolved.into_owned(),
+                    destination[without_anchor.len()..].to_owned(),
+                ))
+            }
+            None => Some((resolved.to_owned(), anchor.to_owned())),
+        }
+    }
+
+    /// Whether `anchor` exists on the page served for `resolved`, or on the page that `resolved`
+    /// redirects to.
+    fn is_anchor_defined(
+        &self,
+        resolved: &str,
+        anchor: &str,
+        profile: ServerProfile,
+        anchor_checks: &AnchorChecks,
+    ) -> bool {
+        let (resolved, anchor) = match self.anchor_target(resolved, anchor, profile) {
+            Some(target) => target,
+            None => return true,
+        };
+
+        let (anchor, directives) = split_directives(&anchor);
+
+        // Text fragments on files other than HTML pages can't be checked.
+        if let (Some(directives), Some(text)) =
+            (directives, anchor_checks.page_texts.get(&resolved))
+        {
+            if !directives_match(directives, text) {
+                return false;
+            }
+        }
+
+        // Anchors on hrefs that are only defined by a redirect pattern can't be checked.
+        anchor_checks.is_always_valid(anchor)
+            || !self.is_defined_exactly(&resolved)
+            || self.is_defined(&format!("{}{}", resolved, anchor))
+            || (resolved.ends_with(".pdf") && self.is_pdf_anchor_defined(&resolved, anchor))
+    }
+
+    /// PDF fragment identifiers may combine several parameters, as in `#page=3&zoom=200`. Only
+    /// pages and named destinations need to exist.
+    fn is_pdf_anchor_defined(&self, resolved: &str, anchor: &str) -> bool {
+        anchor
+            .trim_start_matches('#')
+            .split('&')
+            .filter(|parameter| is_checked_pdf_parameter(parameter))
+            .all(|parameter| self.is_defined(&format!("{}#{}", resolved, parameter)))
+    }
+
+    /// Follow redirects starting at `href`. Returns every href visited after `href` itself, and
+    /// whether the redirects end up in a loop.
+    fn follow_redirects(&self, href: &str, profile: ServerProfile) -> (Vec<String>, bool) {
+        let mut hops = Vec::new();
+        let mut visited = BTreeSet::new();
+        let mut current = href.to_owned();
+
+        loop {
+            let without_anchor = Href(&current).without_anchor().0;
+            let anchor = &current[without_anchor.len()..];
+            let resolved = profile
+                .resolve(without_anchor, |x| self.is_defined(x))
+                .map_or_else(|| without_anchor.to_owned(), |x| x.into_owned());
+
+            let redirect = match self.redirects.get(&resolved) {
+                Some(redirect) => redirect,
+                None => return (hops, false),
+            };
+
+            if !visited.insert(resolved) {
+                return (hops, true);
+            }
+
+            // Browsers keep the anchor across redirects, unless the destination has its own.
+            let mut next = redirect.destination.clone();
+            if !next.contains('#') {
+                next.push_str(anchor);
+            }
+
+            hops.push(next.clone());
+            current = next;
+        }
+    }
+
+    /// Report redirect loops, and redirects that take more than `max_chain` hops to reach their
+    /// final destination.
+    pub fn redirect_diagnostics(
+        &self,
+        profile: ServerProfile,
+        max_chain: Option<usize>,
+    ) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+
+        for (source, redirect) in &self.redirects {
+            let (hops, is_loop) = self.follow_redirects(source, profile);
+
+            let kind = if is_loop {
+                DiagnosticKind::RedirectLoop
+            } else if matches!(max_chain, Some(max_chain) if hops.len() > max_chain) {
+                DiagnosticKind::RedirectChain
+            } else {
+                continue;
+            };
+
+            let mut message = if is_loop {
+                "redirect loop: ".to_owned()
+            } else {
+                format!("redirect chain of {} hops: ", hops.len())
+            };
+
+            message.push('/');
+            message.push_str(source);
+            for hop in &hops {
+                message.push_str(" -> /");
+                message.push_str(hop);
+            }
+
+            diagnostics.push(Diagnostic {
+                kind,
+                path: redirect.path.clone(),
+                message,
+            });
+        }
+
+        diagnostics
+    }
+
+    /// The href that the server serves for `href`, or `href` itself if it is broken.
+    fn resolve<'h>(&self, href: &'h str, profile: ServerProfile) -> Cow<'h, str> {
+        let without_anchor = Href(href).without_anchor().0;
+        profile
+            .resolve(without_anchor, |x| self.is_defined(x))
+            .unwrap_or(Cow::Borrowed(without_anchor))
+    }
+
+    /// Report HTML pages that are not listed in any sitemap, unless they just redirect elsewhere.
+    /// Reported on the first sitemap, nothing is reported for sites without one.
+    pub fn sitemap_diagnostics(&self, profile: ServerProfile) -> Vec<Diagnostic> {
+        let mut sitemap_path: Option<&Arc<PathBuf>> = None;
+        let mut listed_pages = BTreeSet::new();
+
+        for relation in &self.relations {
+            if relation.kind == RelationKind::SitemapEntry {
+                listed_pages.insert(self.resolve(&relation.target, profile));
+                if !matches!(sitemap_path, Some(path) if *path <= relation.path) {
+                    sitemap_path = Some(&relation.path);
+                }
+            }
+        }
+
+        let sitemap_path = match sitemap_path {
+            Some(path) => path,
+            None => return Vec::new(),
+        };
+
+        let mut pages: Vec<_> = self
+            .pages
+            .iter()
+            .filter(|page| {
+                !listed_pages.contains(page.as_str()) && !self.redirects.contains_key(*page)
+            })
+            .collect();
+        pages.sort();
+
+        pages
+            .into_iter()
+            .map(|page| Diagnostic {
+                kind: DiagnosticKind::MissingFromSitemap,
+                path: sitemap_path.clone(),
+                message: format!("page /{} is not listed in the sitemap", page),
+            })
+            .collect()
+    }
+
+    /// Report link relations that are inconsistent across documents: Canonical links to pages
+    /// that declare another page as canonical, and hreflang alternates and prev/next links that
+    /// are not reciprocated by the page they point to.
+    pub fn relation_diagnostics(&self, profile: ServerProfile) -> Vec<Diagnostic> {
+        let pages: BTreeSet<&str> = self.pages.iter().map(String::as_str).collect();
+        let mut canonicals = BTreeMap::new();
+        let mut alternates: BTreeMap<&str, BTreeSet<Cow<'_, str>>> = BTreeMap::new();
+        let mut prevs = BTreeMap::new();
+        let mut nexts = BTreeMap::new();
+
+        for relation in &self.relations {
+            let source = relation.source.as_str();
+            let target = self.resolve(&relation.target, profile);
+            match relation.kind {
+                RelationKind::Canonical => {
+                    canonicals.insert(source, target);
+                }
+                RelationKind::Alternate => {
+                    alternates.entry(source).or_default().insert(target);
+                }
+                RelationKind::Prev => {
+                    prevs.insert(source, target);
+                }
+                RelationKind::Next => {
+                    nexts.insert(source, target);
+                }
+                RelationKind::SitemapEntry => {}
+            }
+        }
+
+        let mut diagnostics = Vec::new();
+
+        for relation in &self.relations {
+            let source = relation.source.as_str();
+            let target = self.resolve(&relation.target, profile);
+
+            // Broken links are reported as such, and there is nothing to check on other files.
+            if target == source || !pages.contains(&*target) {
+                continue;
+            }
+
+            let (kind, message) = match relation.kind {
+                RelationKind::Canonical => match canonicals.get(&*target) {
+                    Some(canonical) if *canonical != target => (
+                        DiagnosticKind::BadCanonical,
+                        format!(
+                            "canonical link /{} is not canonical itself, it points to /{}",
+                            target, canonical
+                        ),
+                    ),
+                    _ => continue,
+                },
+                RelationKind::Alternate => match alternates.get(&*target) {
+                    Some(back) if back.contains(source) => continue,
+                    _ => (
+                        DiagnosticKind::BadHreflang,
+                        format!(
+                            "hreflang alternate /{} ({}) does not link back to /{}",
+                            target,
+                            relation.hreflang.as_deref().unwrap_or_default(),
+                            source
+                        ),
+                    ),
+                },
+                RelationKind::Prev | RelationKind::Next => {
+                    let (name, back_name, backs) = if relation.kind == RelationKind::Prev {
+                        ("previous", "next", &nexts)
+                    } else {
+                        ("next", "previous", &prevs)
+                    };
+
+                    let message = match backs.get(&*target) {
+                        Some(back) if back == source => continue,
+                        Some(back) => format!(
+                            "{} page /{} has /{} as {} page instead of /{}",
+                            name, target, back, back_name, source
+                        ),
+                        None => format!(
+                            "{} page /{} has no {} page, expected /{}",
+                            name, target, back_name, source
+                        ),
+                    };
+
+                    (DiagnosticKind::BadPagination, message)
+                }
+                RelationKind::SitemapEntry => continue,
+            };
+
+            diagnostics.push(Diagnostic {
+                kind,
+                path: relation.path.clone(),
+                message,
+            });
+        }
+
+        diagnostics
+    }
+
+    /// Pages that text fragments (`#:~:text=`) point to. Their visible text needs to be passed to
+    /// `get_broken_links` as `AnchorChecks::page_texts`.
+    pub fn text_fragment_pages(&self, profile: ServerProfile) -> BTreeSet<String> {
+        let mut pages = BTreeSet::new();
+
+        for (href, state) in self.links.iter() {
+            if let LinkState::Undefined(_) = state {
+                let href = unsafe { String::from_utf8_unchecked(href) };
+                let without_anchor = Href(&href).without_anchor().0;
+                let anchor = &href[without_anchor.len()..];
+
+                if split_directives(anchor).1.is_none() {
+                    continue;
+                }
+
+                if let Some(resolved) = profile.resolve(without_anchor, |x| self.is_defined(x)) {
+                    if let Some((page, _)) = self.anchor_target(&resolved, anchor, profile) {
+                        pages.insert(page);
+                    }
+                }
+            }
+        }
+
+        pages
+    }
+
+    pub fn get_broken_links(
+        &self,
+        profile: ServerProfile,
+        anchor_checks: &AnchorChecks,
+        unicode_form: Option<UnicodeForm>,
+    ) -> impl Iterator<Item = BrokenLink<P>> {
         let mut broken_links = Vec::new();
+        // Only needed for broken links, which are hopefully rare.
+        let mut case_folded = None;
+        let mut normalized = None;
 
         for (href, state) in self.links.iter() {
             if let LinkState::Undefined(links) = state {
                 let href = unsafe { String::from_utf8_unchecked(href) };
-                let hard_404 = if check_anchors {
-                    !matches!(
-                        self.links.get(&Href(&href).without_anchor()),
-                        Some(&LinkState::Defined)
-                    )
-                } else {
-                    true
+                let without_anchor = Href(&href).without_anchor().0;
+                let anchor = &href[without_anchor.len()..];
+
+                // The href as written may not be defined, but the server may still serve a file
+                // for it.
+                let mut resolved = profile
+                    .resolve(without_anchor, |x| self.is_defined(x))
+                    .map(Cow::into_owned);
+
+                if resolved.is_none() && profile.is_case_insensitive() {
+                    let case_folded =
+                        case_folded.get_or_insert_with(|| self.folded_index(fold_case));
+                    resolved = resolve_folded(case_folded, without_anchor, profile, fold_case);
+                }
+
+                if let (None, Some(form)) = (&resolved, unicode_form) {
+                    // Reported by `unicode_diagnostics` instead.
+                    let normalize = |href: &str| form.normalize(href);
+                    let normalized = normalized.get_or_insert_with(|| self.folded_index(normalize));
+                    resolved = resolve_folded(normalized, without_anchor, profile, normalize);
+                }
+
+                let (hard_404, case_mismatch) = match resolved {
+                    None => {
+                        let case_folded =
+                            case_folded.get_or_insert_with(|| self.folded_index(fold_case));
+                        let case_mismatch =
+                            resolve_folded(case_folded, without_anchor, profile, fold_case);
+                        (true, case_mismatch)
+                    }
+                    Some(resolved) => {
+                        // The server serves a file for this href, so at most the anchor is broken.
+                        if anchor.is_empty()
+                            || self.is_anchor_defined(&resolved, anchor, profile, anchor_checks)
+                        {
+                            continue;
+                        }
+
+                        let case_folded =
+                            case_folded.get_or_insert_with(|| self.folded_index(fold_case));
+                        let case_mismatch = case_folded
+                            .get(&format!("{}{}", resolved, anchor).to_lowercase())
+                            .cloned();
+                        (false, case_mismatch)
+                    }
                 };
 
                 for (path, paragraph) in links.iter() {
@@ -153,6 +636,7 @@ impl<P: Copy + PartialEq> BrokenLinkCollector<P> {
                             paragraph: *paragraph,
                             href: href.clone(),
                         },
+                        case_mismatch: case_mismatch.clone(),
                     });
                 }
             }
@@ -161,7 +645,116 @@ impl<P: Copy + PartialEq> BrokenLinkCollector<P> {
         broken_links.into_iter()
     }
 
+    /// All defined hrefs by `fold(href)`, for finding links that only differ from a defined href
+    /// in ways that `fold` removes, such as case.
+    fn folded_index(&self, fold: impl Fn(&str) -> String) -> BTreeMap<String, String> {
+        let mut index = BTreeMap::new();
+
+        for (href, state) in self.links.iter() {
+            if let LinkState::Defined = state {
+                let href = unsafe { String::from_utf8_unchecked(href) };
+                index.entry(fold(&href)).or_insert(href);
+            }
+        }
+
+        index
+    }
+
+    /// Links that servers which ignore case, such as IIS, serve only because they ignore case.
+    /// These are warnings, as the site breaks when moved to another server.
+    pub fn case_diagnostics(&self, profile: ServerProfile) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+
+        if !profile.is_case_insensitive() {
+            // Such links are broken links already.
+            return diagnostics;
+        }
+
+        let case_folded = self.folded_index(fold_case);
+
+        for (href, state) in self.links.iter() {
+            if let LinkState::Undefined(links) = state {
+                let href = unsafe { String::from_utf8_unchecked(href) };
+                let without_anchor = Href(&href).without_anchor().0;
+
+                if profile
+                    .resolve(without_anchor, |x| self.is_defined(x))
+                    .is_some()
+                {
+                    continue;
+                }
+
+                if let Some(resolved) =
+                    resolve_folded(&case_folded, without_anchor, profile, fold_case)
+                {
+                    for (path, _) in links.iter() {
+                        diagnostics.push(Diagnostic {
+                            kind: DiagnosticKind::CaseMismatch,
+                            path: path.clone(),
+                            message: format!(
+                                "link /{} only matches /{} if case is ignored",
+                                href, resolved
+                            ),
+                        });
+                    }
+                }
+            }
+        }
+
+        diagnostics
+    }
+
+    /// Links that are only defined once both the link and the file name are normalized to `form`,
+    /// for example a composed `é` in the link and a decomposed one in the file name. Servers
+    /// compare the bytes, so these are warnings.
+    pub fn unicode_diagnostics(
+        &self,
+        profile: ServerProfile,
+        form: UnicodeForm,
+    ) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let normalize = |href: &str| form.normalize(href);
+        let normalized = self.folded_index(normalize);
+
+        for (href, state) in self.links.iter() {
+            if let LinkState::Undefined(links) = state {
+                let href = unsafe { String::from_utf8_unchecked(href) };
+                let without_anchor = Href(&href).without_anchor().0;
+
+                if profile
+                    .resolve(without_anchor, |x| self.is_defined(x))
+                    .is_some()
+                {
+                    continue;
+                }
+
+                if let Some(resolved) =
+                    resolve_folded(&normalized, without_anchor, profile, normalize)
+                {
+                    for (path, _) in links.iter() {
+                        diagnostics.push(Diagnostic {
+                            kind: DiagnosticKind::UnicodeMismatch,
+                            path: path.clone(),
+                            message: format!(
+                                "link /{} only matches /{} after {} normalization",
+                                form.escape_unnormalized(&href),
+                                form.escape_unnormalized(&resolved),
+                                form
+                            ),
+                        });
+                    }
+                }
+            }
+        }
+
+        diagnostics
+    }
+
     pub fn used_links_count(&self) -> usize {
         self.used_link_count
     }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
 }
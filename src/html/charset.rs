@@ -0,0 +1,234 @@
+//! Character encodings of HTML documents other than UTF-8, such as windows-1252 or Shift_JIS.
+//!
+//! Like browsers, the encoding is taken from a byte order mark, or else from `<meta charset>` or
+//! `<meta http-equiv="Content-Type">` in the first bytes of the document. Documents are
+//! transcoded to UTF-8 before they are tokenized, so that hrefs and ids are percent-decoded and
+//! compared as the characters the author meant.
+//!
+//! https://html.spec.whatwg.org/multipage/parsing.html#prescan-a-byte-stream-to-determine-its-encoding
+
+use std::io::{self, Read};
+
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+/// How many bytes at the start of a document are searched for `<meta charset>`.
+const PRESCAN_LEN: u64 = 1024;
+
+/// Transcode a document to UTF-8 if it declares another encoding. Documents without a byte order
+/// mark or declaration are passed through as they are, as UTF-8.
+pub fn decode_read<R: Read>(read: R) -> io::Result<impl Read> {
+    let mut prefix = Vec::new();
+    let mut read = read;
+    (&mut read).take(PRESCAN_LEN).read_to_end(&mut prefix)?;
+
+    Ok(DecodeReaderBytesBuilder::new()
+        .encoding(meta_encoding(&prefix))
+        .bom_override(true)
+        .utf8_passthru(true)
+        .strip_bom(true)
+        .build(io::Cursor::new(prefix).chain(read)))
+}
+
+/// The encoding declared by a `<meta>` tag in `prefix`, unless that is UTF-8.
+fn meta_encoding(prefix: &[u8]) -> Option<&'static Encoding> {
+    let mut rest = prefix;
+
+    while let Some(start) = find_ignore_ascii_case(rest, b"<meta") {
+        rest = &rest[start + b"<meta".len()..];
+
+        if !matches!(rest.first(), Some(c) if c.is_ascii_whitespace() || *c == b'/') {
+            continue;
+        }
+
+        let (attributes, after) = parse_attributes(rest);
+        rest = after;
+
+        let label = match attributes.iter().find(|(name, _)| name == b"charset") {
+            Some((_, value)) => Some(value.as_slice()),
+            None if attributes.iter().any(|(name, value)| {
+                name == b"http-equiv" && value.eq_ignore_ascii_case(b"content-type")
+            }) =>
+            {
+                attributes
+                    .iter()
+                    .find(|(name, _)| name == b"content")
+                    .and_then(|(_, content)| charset_from_content(content))
+            }
+            None => None,
+        };
+
+        if let Some(encoding) = label.and_then(Encoding::for_label) {
+            // A document that could declare itself as UTF-16 in ASCII is not UTF-16, and
+            // x-user-defined is treated as windows-1252.
+            return match encoding {
+                encoding if encoding == UTF_8 || encoding == UTF_16BE || encoding == UTF_16LE => {
+                    None
+                }
+                encoding if encoding.name() == "x-user-defined" => Some(WINDOWS_1252),
+                encoding => Some(encoding),
+            };
+        }
+    }
+
+    None
+}
+
+fn find_ignore_ascii_case(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle))
+}
+
+/// The lowercase name and the value of an attribute.
+type Attribute = (Vec<u8>, Vec<u8>);
+
+/// Parse the attributes of a tag up to the closing `>`. Returns the attributes and the input
+/// after the tag.
+fn parse_attributes(mut input: &[u8]) -> (Vec<Attribute>, &[u8]) {
+    let mut attributes = Vec::new();
+
+    loop {
+        input = trim_start(input);
+
+        let name_len = input
+            .iter()
+            .position(|c| c.is_ascii_whitespace() || matches!(c, b'=' | b'>' | b'/'))
+            .unwrap_or(input.len());
+
+        if name_len == 0 {
+            match input.first() {
+                Some(b'/') => {
+                    input = &input[1..];
+                    continue;
+                }
+                Some(b'>') => return (attributes, &input[1..]),
+                _ => return (attributes, input),
+            }
+        }
+
+        let name = input[..name_len].to_ascii_lowercase();
+        input = trim_start(&input[name_len..]);
+
+        let mut value = Vec::new();
+        if let Some(after_equals) = input.strip_prefix(b"=") {
+            input = trim_start(after_equals);
+
+            let value_len = match input.first() {
+                Some(quote @ (b'"' | b'\'')) => {
+                    input = &input[1..];
+                    let len = input.iter().position(|c| c == quote).unwrap_or(input.len());
+                    value.extend(&input[..len]);
+                    (len + 1).min(input.len())
+                }
+                _ => {
+                    let len = input
+                        .iter()
+                        .position(|c| c.is_ascii_whitespace() || *c == b'>')
+                        .unwrap_or(input.len());
+                    value.extend(&input[..len]);
+                    len
+                }
+            };
+            input = &input[value_len..];
+        }
+
+        attributes.push((name, value));
+    }
+}
+
+fn trim_start(input: &[u8]) -> &[u8] {
+    let len = input
+        .iter()
+        .position(|c| !c.is_ascii_whitespace())
+        .unwrap_or(input.len());
+    &input[len..]
+}
+
+/// Extract the charset from the `content` of `<meta http-equiv="Content-Type">`, such as
+/// `text/html; charset=Shift_JIS`.
+///
+/// https://html.spec.whatwg.org/multipage/urls-and-fetching.html#algorithm-for-extracting-a-character-encoding-from-a-meta-element
+fn charset_from_content(content: &[u8]) -> Option<&[u8]> {
+    let start = find_ignore_ascii_case(content, b"charset")?;
+    let rest = trim_start(&content[start + b"charset".len()..]);
+    let rest = trim_start(rest.strip_prefix(b"=")?);
+
+    match rest.first() {
+        Some(quote @ (b'"' | b'\'')) => {
+            let rest = &rest[1..];
+            Some(&rest[..rest.iter().position(|c| c == quote)?])
+        }
+        _ => {
+            let len = rest
+                .iter()
+                .position(|c| c.is_ascii_whitespace() || *c == b';')
+                .unwrap_or(rest.len());
+            Some(&rest[..len])
+        }
+    }
+}
+
+#[cfg(test)]
+fn decode(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    decode_read(input)
+        .unwrap()
+        .read_to_end(&mut output)
+        .unwrap();
+    output
+}
+
+#[test]
+fn test_meta_encoding() {
+    assert_eq!(
+        meta_encoding(b"<meta charset=\"windows-1252\">"),
+        Some(WINDOWS_1252)
+    );
+    assert_eq!(
+        meta_encoding(b"<head><META CHARSET=shift_jis>"),
+        Some(encoding_rs::SHIFT_JIS)
+    );
+    assert_eq!(
+        meta_encoding(
+            b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset='iso-8859-1'\">"
+        ),
+        Some(WINDOWS_1252)
+    );
+    assert_eq!(
+        meta_encoding(b"<meta content=\"text/html; charset=euc-jp\" http-equiv=content-type />"),
+        Some(encoding_rs::EUC_JP)
+    );
+    assert_eq!(meta_encoding(b"<meta charset=utf-8>"), None);
+    assert_eq!(meta_encoding(b"<meta charset=utf-16>"), None);
+    assert_eq!(meta_encoding(b"<meta charset=bogus>"), None);
+    assert_eq!(meta_encoding(b"<meta content=\"charset=big5\">"), None);
+    assert_eq!(meta_encoding(b"<metadata charset=big5>"), None);
+    assert_eq!(
+        meta_encoding(b"<meta name=viewport content=x><meta charset=big5>"),
+        Some(encoding_rs::BIG5)
+    );
+}
+
+#[test]
+fn test_decode_read() {
+    assert_eq!(
+        decode(b"<meta charset=windows-1252><a href=\"caf\xe9.html\">"),
+        "<meta charset=windows-1252><a href=\"café.html\">".as_bytes()
+    );
+    assert_eq!(
+        decode(b"<meta charset=shift_jis><a id=\"\x93\xfa\x96\x7b\">"),
+        "<meta charset=shift_jis><a id=\"日本\">".as_bytes()
+    );
+    assert_eq!(decode(b"\xff\xfe<\0a\0>\0\xe9\0"), "<a>é".as_bytes());
+    assert_eq!(decode(b"\xef\xbb\xbf<a>"), b"<a>");
+
+    // Invalid UTF-8 is left for the tokenizer to report.
+    assert_eq!(
+        decode(b"<a href=\"caf\xe9.html\">"),
+        b"<a href=\"caf\xe9.html\">"
+    );
+}
@@ -0,0 +1,133 @@
+//! Precompressed files such as `page.html.gz` and `page.html.br`, which servers such as nginx
+//! with `gzip_static` serve for `page.html`.
+
+use std::io::Read;
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+/// Buffer size of the brotli decoder, as recommended by the `brotli` crate.
+const BROTLI_BUFFER_SIZE: usize = 4096;
+
+/// Extensions of the files that are read and that servers serve precompressed variants of. Other
+/// files such as `site-1.0.tar.gz` are downloads of their own.
+static PRECOMPRESSED_EXTENSIONS: &[&str] =
+    &["htm", "html", "xhtml", "css", "svg", "xml", "rss", "atom"];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Brotli,
+}
+
+impl Compression {
+    /// The compression of a precompressed file. Only files with the extension of a file that is
+    /// read before `.gz` or `.br` count, so that `data.gz` and `site-1.0.tar.gz` stay as they are.
+    pub fn from_path(path: &Path) -> Option<Compression> {
+        let compression = match path.extension()?.to_str()? {
+            "gz" => Compression::Gzip,
+            "br" => Compression::Brotli,
+            _ => return None,
+        };
+
+        let extension = Path::new(path.file_stem()?).extension()?.to_str()?;
+        if !PRECOMPRESSED_EXTENSIONS.contains(&extension) {
+            return None;
+        }
+
+        Some(compression)
+    }
+
+    /// Whether a server serves another file than the precompressed file at `path`, preferring the
+    /// original, then `.br`, then `.gz`. `exists` tells whether a file of the site exists.
+    pub fn is_shadowed(self, path: &Path, exists: impl Fn(&Path) -> bool) -> bool {
+        let original = path.with_extension("");
+        if exists(&original) {
+            return true;
+        }
+
+        match self {
+            Compression::Brotli => false,
+            Compression::Gzip => {
+                let mut brotli = original.into_os_string();
+                brotli.push(".br");
+                exists(Path::new(&brotli))
+            }
+        }
+    }
+
+    pub fn decompress<'r, R: Read + 'r>(self, read: R) -> Box<dyn Read + 'r> {
+        match self {
+            Compression::Gzip => Box::new(GzDecoder::new(read)),
+            Compression::Brotli => Box::new(brotli::Decompressor::new(read, BROTLI_BUFFER_SIZE)),
+        }
+    }
+}
+
+#[test]
+fn test_from_path() {
+    assert_eq!(
+        Compression::from_path(Path::new("public/page.html.gz")),
+        Some(Compression::Gzip)
+    );
+    assert_eq!(
+        Compression::from_path(Path::new("public/style.css.br")),
+        Some(Compression::Brotli)
+    );
+    assert_eq!(Compression::from_path(Path::new("public/data.gz")), None);
+    assert_eq!(
+        Compression::from_path(Path::new("public/downloads/site-1.0.tar.gz")),
+        None
+    );
+    assert_eq!(Compression::from_path(Path::new("public/page.html")), None);
+}
+
+#[test]
+fn test_is_shadowed() {
+    let exists = |files: &'static [&'static str]| {
+        move |path: &Path| files.iter().any(|f| path == Path::new(f))
+    };
+
+    let files = exists(&["page.html", "page.html.gz", "page.html.br"]);
+    assert!(Compression::Gzip.is_shadowed(Path::new("page.html.gz"), files));
+    assert!(Compression::Brotli.is_shadowed(Path::new("page.html.br"), files));
+
+    let files = exists(&["page.html.gz", "page.html.br"]);
+    assert!(Compression::Gzip.is_shadowed(Path::new("page.html.gz"), files));
+    assert!(!Compression::Brotli.is_shadowed(Path::new("page.html.br"), files));
+
+    // Files in an archive are named with `/` on every platform.
+    let files = exists(&["site.tar.gz!/page.html"]);
+    assert!(Compression::Gzip.is_shadowed(Path::new("site.tar.gz!/page.html.gz"), files));
+
+    let files = exists(&["page.html.gz"]);
+    assert!(!Compression::Gzip.is_shadowed(Path::new("page.html.gz"), files));
+}
+
+#[test]
+fn test_decompress() {
+    use std::io::Write;
+
+    let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gzip.write_all(b"<a href=foo>").unwrap();
+    let gzip = gzip.finish().unwrap();
+
+    let mut brotli = brotli::CompressorWriter::new(Vec::new(), BROTLI_BUFFER_SIZE, 9, 22);
+    brotli.write_all(b"<a href=bar>").unwrap();
+    let brotli = brotli.into_inner();
+
+    for (compression, compressed, expected) in [
+        (Compression::Gzip, gzip, "<a href=foo>"),
+        (Compression::Brotli, brotli, "<a href=bar>"),
+    ] {
+        let mut contents = String::new();
+        compression
+            .decompress(&compressed[..])
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, expected);
+    }
+}
@@ -0,0 +1,171 @@
+use bumpalo::collections::Vec as BumpVec;
+use bumpalo::Bump;
+
+use crate::html::{Document, Link, ServerConfig};
+
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+#[inline]
+fn starts_with_ignore_case(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.len() >= needle.len() && haystack[..needle.len()].eq_ignore_ascii_case(needle)
+}
+
+#[inline]
+fn is_ident_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'-' || c == b'_' || c >= 0x80
+}
+
+fn skip_whitespace(css: &[u8], mut i: usize) -> usize {
+    while i < css.len() && css[i].is_ascii_whitespace() {
+        i += 1;
+    }
+    i
+}
+
+/// Parse a quoted string starting at `css[i]`, return its contents and the index after the
+/// closing quote.
+fn parse_string(css: &[u8], i: usize) -> (&[u8], usize) {
+    let quote = css[i];
+    let start = i + 1;
+    let mut end = start;
+    while end < css.len() && css[end] != quote {
+        if css[end] == b'\\' {
+            end += 1;
+        }
+        end += 1;
+    }
+    let end = end.min(css.len());
+    (&css[start..end], (end + 1).min(css.len()))
+}
+
+/// Call `f` for every URL referenced in a stylesheet, that is every `url(...)` and every
+/// `@import "..."`.
+///
+/// This is not a full CSS tokenizer. It knows just enough about comments and strings to not find
+/// URLs inside of them. Escape sequences within URLs are not decoded.
+pub fn for_each_url<'s>(css: &'s [u8], mut f: impl FnMut(&'s [u8])) {
+    let mut i = 0;
+
+    while i < css.len() {
+        match css[i] {
+            b'/' if css[i..].starts_with(b"/*") => {
+                i = match css[i + 2..].windows(2).position(|w| w == b"*/") {
+                    Some(end) => i + 2 + end + 2,
+                    None => css.len(),
+                };
+            }
+            b'"' | b'\'' => {
+                i = parse_string(css, i).1;
+            }
+            b'@' if starts_with_ignore_case(&css[i + 1..], b"import") => {
+                i = skip_whitespace(css, i + 7);
+                if i < css.len() && matches!(css[i], b'"' | b'\'') {
+                    let (url, next) = parse_string(css, i);
+                    f(url);
+                    i = next;
+                }
+            }
+            b'u' | b'U'
+                if starts_with_ignore_case(&css[i..], b"url(")
+                    && (i == 0 || !is_ident_char(css[i - 1])) =>
+            {
+                i = skip_whitespace(css, i + 4);
+                if i < css.len() && matches!(css[i], b'"' | b'\'') {
+                    let (url, next) = parse_string(css, i);
+                    f(url);
+                    i = next;
+                } else {
+                    let start = i;
+                    while i < css.len() && css[i] != b')' {
+                        i += 1;
+                    }
+                    f(&css[start..i]);
+                }
+            }
+            _ => i += 1,
+        }
+    }
+}
+
+/// Extract all links from a stylesheet (or style attribute) and push them into `link_buf`.
+///
+/// `base` is the resolved `<base href>` for inline styles within HTML, see
+/// `Document::join_with_base`.
+pub fn push_css_links<'a, 'l, P>(
+    document: &Document,
+    arena: &'a Bump,
+    server_config: &ServerConfig,
+    base: Option<&'a str>,
+    check_anchors: bool,
+    link_buf: &mut BumpVec<'a, Link<'l, P>>,
+    css: &[u8],
+) where
+    'a: 'l,
+{
+    for_each_url(css, |url| {
+        let url = match std::str::from_utf8(url) {
+            Ok(url) => url.trim(),
+            Err(_) => return,
+        };
+
+        // Fragment-only URLs such as `fill: url(#gradient)` always refer to the document that
+        // uses the stylesheet, not to the stylesheet itself.
+        if url.is_empty() || url.starts_with('#') {
+            return;
+        }
+
+        document.push_used_link(arena, server_config, base, check_anchors, link_buf, url);
+    });
+}
+
+#[cfg(test)]
+fn urls(css: &str) -> Vec<&str> {
+    let mut rv = Vec::new();
+    for_each_url(css.as_bytes(), |url| {
+        rv.push(std::str::from_utf8(url).unwrap().trim())
+    });
+    rv
+}
+
+#[test]
+fn test_css_urls_basic() {
+    assert_eq!(
+        urls(
+            r#"
+            @import "base.css";
+            @IMPORT url(print.css) print;
+            @font-face { src: url("fonts/inter.woff2") format("woff2"), url( 'fonts/inter.woff' ); }
+            body { background: URL(  img/bg.png  ) no-repeat; }
+            "#
+        ),
+        &[
+            "base.css",
+            "print.css",
+            "fonts/inter.woff2",
+            "fonts/inter.woff",
+            "img/bg.png"
+        ]
+    );
+}
+
+#[test]
+fn test_css_urls_comments_and_strings() {
+    assert_eq!(
+        urls(
+            r#"
+            /* background: url(commented.png); */
+            a::after { content: "url(not-a-url.png)"; }
+            .x { mask: curl(foo.png); background: url(real.png) }
+            "#
+        ),
+        &["real.png"]
+    );
+}
+
+#[test]
+fn test_css_urls_unterminated() {
+    assert_eq!(urls("a { background: url(foo.png"), &["foo.png"]);
+    assert_eq!(urls("@import 'foo.css"), &["foo.css"]);
+    assert_eq!(urls("/* url(foo.png)"), Vec::<&str>::new());
+}
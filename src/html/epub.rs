@@ -0,0 +1,259 @@
+//! The package files of EPUB publications, see `--epub`: `META-INF/container.xml`, which points
+//! to the OPF package document, the package document with its manifest and spine, and the
+//! EPUB 2 table of contents (NCX).
+//!
+//! https://www.w3.org/TR/epub-33/
+
+use anyhow::Error;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+/// Where the container lists the package documents of a publication.
+pub const CONTAINER_HREF: &str = "META-INF/container.xml";
+
+/// Media types that spine items can have without a fallback.
+static CONTENT_DOCUMENT_TYPES: &[&str] = &["application/xhtml+xml", "image/svg+xml"];
+
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct ManifestItem {
+    pub id: String,
+    pub href: String,
+    pub media_type: String,
+    pub properties: String,
+    pub fallback: Option<String>,
+}
+
+/// What is checked of an OPF package document.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Package {
+    pub version: String,
+    pub manifest: Vec<ManifestItem>,
+    /// The `idref`s of the spine, in reading order.
+    pub spine: Vec<String>,
+    /// The id of the NCX, from `<spine toc>`.
+    pub toc: Option<String>,
+}
+
+impl Package {
+    /// Problems with the spine and navigation that don't show up as broken links, as they refer
+    /// to manifest items by id.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let item = |id: &str| self.manifest.iter().find(|item| item.id == id);
+
+        for idref in &self.spine {
+            match item(idref) {
+                None => problems.push(format!(
+                    "spine refers to missing manifest item \"{}\"",
+                    idref
+                )),
+                Some(item)
+                    if item.fallback.is_none()
+                        && !CONTENT_DOCUMENT_TYPES.contains(&item.media_type.as_str()) =>
+                {
+                    problems.push(format!(
+                        "spine item \"{}\" is not a content document ({})",
+                        idref, item.media_type
+                    ))
+                }
+                Some(_) => (),
+            }
+        }
+
+        if let Some(ref toc) = self.toc {
+            if item(toc).is_none() {
+                problems.push(format!(
+                    "<spine toc=\"{}\"> refers to missing manifest item",
+                    toc
+                ));
+            }
+        }
+
+        let has_nav = self
+            .manifest
+            .iter()
+            .any(|item| item.properties.split_whitespace().any(|p| p == "nav"));
+
+        if self.version.starts_with('3') && !has_nav {
+            problems.push("no manifest item has properties=\"nav\"".to_owned());
+        }
+
+        problems
+    }
+}
+
+fn attribute(e: &BytesStart, name: &[u8]) -> Result<Option<String>, Error> {
+    for attribute in e.attributes() {
+        let attribute = attribute?;
+        if attribute.key == name {
+            return Ok(Some(
+                String::from_utf8_lossy(&attribute.unescaped_value()?).into_owned(),
+            ));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Call `f` with every start or empty tag of an XML document, by local name.
+fn for_each_tag(
+    xml: &[u8],
+    mut f: impl FnMut(&[u8], &BytesStart) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let mut reader = Reader::from_reader(xml);
+    reader.trim_text(true);
+
+    let mut buf = Vec::new();
+
+    loop {
+        match reader.read_event(&mut buf)? {
+            Event::Start(ref e) | Event::Empty(ref e) => f(e.local_name(), e)?,
+            Event::Eof => break,
+            _ => {}
+        }
+
+        buf.clear();
+    }
+
+    Ok(())
+}
+
+/// The paths of the package documents listed by `META-INF/container.xml`, relative to the root
+/// of the publication.
+pub fn container_rootfiles(xml: &[u8]) -> Result<Vec<String>, Error> {
+    let mut rootfiles = Vec::new();
+
+    for_each_tag(xml, |name, e| {
+        if name == b"rootfile" {
+            rootfiles.extend(attribute(e, b"full-path")?);
+        }
+        Ok(())
+    })?;
+
+    Ok(rootfiles)
+}
+
+pub fn parse_package(xml: &[u8]) -> Result<Package, Error> {
+    let mut package = Package::default();
+
+    for_each_tag(xml, |name, e| {
+        match name {
+            b"package" => package.version = attribute(e, b"version")?.unwrap_or_default(),
+            b"item" => package.manifest.push(ManifestItem {
+                id: attribute(e, b"id")?.unwrap_or_default(),
+                href: attribute(e, b"href")?.unwrap_or_default(),
+                media_type: attribute(e, b"media-type")?.unwrap_or_default(),
+                properties: attribute(e, b"properties")?.unwrap_or_default(),
+                fallback: attribute(e, b"fallback")?,
+            }),
+            b"itemref" => package.spine.extend(attribute(e, b"idref")?),
+            b"spine" => package.toc = attribute(e, b"toc")?,
+            _ => (),
+        }
+        Ok(())
+    })?;
+
+    Ok(package)
+}
+
+/// The targets of all entries of an NCX table of contents, page list or navigation list.
+pub fn ncx_sources(xml: &[u8]) -> Result<Vec<String>, Error> {
+    let mut sources = Vec::new();
+
+    for_each_tag(xml, |name, e| {
+        if name == b"content" {
+            sources.extend(attribute(e, b"src")?);
+        }
+        Ok(())
+    })?;
+
+    Ok(sources)
+}
+
+#[test]
+fn test_container_rootfiles() {
+    assert_eq!(
+        container_rootfiles(
+            br#"<?xml version="1.0"?>
+            <container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+              <rootfiles>
+                <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+              </rootfiles>
+            </container>"#
+        )
+        .unwrap(),
+        &["OEBPS/content.opf"]
+    );
+}
+
+#[test]
+fn test_parse_package() {
+    let package = parse_package(
+        br#"<?xml version="1.0"?>
+        <package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+          <manifest>
+            <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+            <item id="ch1" href="text/ch%201.xhtml" media-type="application/xhtml+xml"/>
+            <item id="cover" href="cover.png" media-type="image/png"/>
+            <item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>
+          </manifest>
+          <spine toc="ncx">
+            <itemref idref="ch1"/>
+            <itemref idref="cover"/>
+            <itemref idref="ch2"/>
+          </spine>
+        </package>"#,
+    )
+    .unwrap();
+
+    assert_eq!(package.version, "3.0");
+    assert_eq!(package.manifest.len(), 4);
+    assert_eq!(package.manifest[1].href, "text/ch%201.xhtml");
+    assert_eq!(package.spine, &["ch1", "cover", "ch2"]);
+    assert_eq!(package.toc.as_deref(), Some("ncx"));
+
+    assert_eq!(
+        package.problems(),
+        &[
+            "spine item \"cover\" is not a content document (image/png)",
+            "spine refers to missing manifest item \"ch2\"",
+        ]
+    );
+
+    let package =
+        parse_package(br#"<package version="3.0"><spine toc="missing"></spine></package>"#)
+            .unwrap();
+
+    assert_eq!(
+        package.problems(),
+        &[
+            "<spine toc=\"missing\"> refers to missing manifest item",
+            "no manifest item has properties=\"nav\"",
+        ]
+    );
+}
+
+#[test]
+fn test_ncx_sources() {
+    assert_eq!(
+        ncx_sources(
+            br#"<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">
+              <navMap>
+                <navPoint id="p1" playOrder="1">
+                  <navLabel><text>Chapter 1</text></navLabel>
+                  <content src="text/ch1.xhtml"/>
+                  <navPoint id="p2" playOrder="2">
+                    <navLabel><text>Section</text></navLabel>
+                    <content src="text/ch1.xhtml#section"/>
+                  </navPoint>
+                </navPoint>
+              </navMap>
+            </ncx>"#
+        )
+        .unwrap(),
+        &["text/ch1.xhtml", "text/ch1.xhtml#section"]
+    );
+}
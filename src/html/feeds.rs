@@ -0,0 +1,234 @@
+//! Files that list URLs of the site for machines: sitemaps, RSS and Atom feeds, and robots.txt.
+
+use anyhow::Error;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+/// Root elements of the XML documents that are read. Other XML files, such as data files, are
+/// ignored.
+static FEED_ROOTS: &[&[u8]] = &[b"urlset", b"sitemapindex", b"rss", b"feed", b"RDF"];
+
+/// A URL found in a sitemap, feed or robots.txt.
+#[derive(Debug, Eq, PartialEq)]
+pub struct FeedUrl {
+    pub url: String,
+    /// Whether this URL is a page listed by a sitemap, as opposed to e.g. a nested sitemap or an
+    /// image.
+    pub is_sitemap_entry: bool,
+}
+
+/// Extract all URLs from a sitemap, sitemap index, RSS or Atom feed into `urls`: `<loc>`, `<link>`
+/// (either its text or its `href`) and `<enclosure url>`. Fails if the document turns out to be
+/// malformed, in which case the URLs found up to that point are still in `urls`.
+pub fn xml_urls(xml: &[u8], urls: &mut Vec<FeedUrl>) -> Result<(), Error> {
+    let mut reader = Reader::from_reader(xml);
+    reader.trim_text(true).check_end_names(false);
+
+    let mut buf = Vec::new();
+    let mut stack: Vec<Vec<u8>> = Vec::new();
+    let mut text: Option<(String, bool)> = None;
+    let mut has_root = false;
+
+    loop {
+        let event = match reader.read_event(&mut buf) {
+            Ok(event) => event,
+            // Not one of our files, it doesn't matter whether it is valid.
+            Err(_) if !has_root => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        match event {
+            Event::Start(ref e) | Event::Empty(ref e) => {
+                if !has_root {
+                    if !FEED_ROOTS.contains(&e.local_name()) {
+                        return Ok(());
+                    }
+                    has_root = true;
+                }
+
+                let mut url_attribute = None;
+                for attribute in e.attributes() {
+                    let attribute = attribute?;
+                    if let (b"link", b"href") | (b"enclosure", b"url") =
+                        (e.local_name(), attribute.key)
+                    {
+                        url_attribute = Some(
+                            String::from_utf8_lossy(&attribute.unescaped_value()?).into_owned(),
+                        );
+                    }
+                }
+
+                let is_start = matches!(event, Event::Start(_));
+
+                if let Some(url) = url_attribute {
+                    urls.push(FeedUrl {
+                        url,
+                        is_sitemap_entry: false,
+                    });
+                } else if is_start && matches!(e.local_name(), b"loc" | b"link") {
+                    let is_sitemap_entry =
+                        e.name() == b"loc" && stack.last().map(Vec::as_slice) == Some(b"url");
+                    text = Some((String::new(), is_sitemap_entry));
+                }
+
+                if is_start {
+                    stack.push(e.name().to_vec());
+                }
+            }
+            Event::Text(ref e) => {
+                if let Some((ref mut url, _)) = text {
+                    url.push_str(&String::from_utf8_lossy(&e.unescaped()?));
+                }
+            }
+            Event::CData(e) => {
+                if let Some((ref mut url, _)) = text {
+                    url.push_str(&String::from_utf8_lossy(&e.into_inner()));
+                }
+            }
+            Event::End(_) => {
+                stack.pop();
+                if let Some((url, is_sitemap_entry)) = text.take() {
+                    let url = url.trim();
+                    if !url.is_empty() {
+                        urls.push(FeedUrl {
+                            url: url.to_owned(),
+                            is_sitemap_entry,
+                        });
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+
+        buf.clear();
+    }
+
+    Ok(())
+}
+
+/// Extract the URLs of all `Sitemap:` lines in a robots.txt.
+pub fn robots_txt_urls(contents: &str) -> impl Iterator<Item = &str> {
+    contents.lines().filter_map(|line| {
+        let line = line.split('#').next().unwrap_or_default();
+        let (key, value) = line.split_once(':')?;
+        if key.trim().eq_ignore_ascii_case("sitemap") {
+            Some(value.trim())
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+fn urls(xml: &str) -> Vec<(String, bool)> {
+    let mut urls = Vec::new();
+    xml_urls(xml.as_bytes(), &mut urls).unwrap();
+    urls.into_iter()
+        .map(|url| (url.url, url.is_sitemap_entry))
+        .collect()
+}
+
+#[test]
+fn test_xml_urls_sitemap() {
+    assert_eq!(
+        urls(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"
+                    xmlns:image="http://www.google.com/schemas/sitemap-image/1.1"
+                    xmlns:xhtml="http://www.w3.org/1999/xhtml">
+              <url>
+                <loc>https://example.com/about/?a=1&amp;b=2</loc>
+                <image:image><image:loc>https://example.com/img.png</image:loc></image:image>
+                <xhtml:link rel="alternate" hreflang="de" href="https://example.com/de/about/"/>
+              </url>
+              <url><loc><![CDATA[https://example.com/blog/]]></loc></url>
+            </urlset>"#
+        ),
+        &[
+            ("https://example.com/about/?a=1&b=2".to_owned(), true),
+            ("https://example.com/img.png".to_owned(), false),
+            ("https://example.com/de/about/".to_owned(), false),
+            ("https://example.com/blog/".to_owned(), true),
+        ]
+    );
+
+    assert_eq!(
+        urls(
+            r#"<sitemapindex>
+              <sitemap><loc>https://example.com/sitemap-1.xml</loc></sitemap>
+            </sitemapindex>"#
+        ),
+        &[("https://example.com/sitemap-1.xml".to_owned(), false)]
+    );
+}
+
+#[test]
+fn test_xml_urls_feeds() {
+    assert_eq!(
+        urls(
+            r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom"><channel>
+              <link>https://example.com/</link>
+              <atom:link href="https://example.com/feed.xml" rel="self"/>
+              <item>
+                <link>https://example.com/posts/hello/</link>
+                <enclosure url="https://example.com/episode.mp3" type="audio/mpeg" length="1"/>
+              </item>
+            </channel></rss>"#
+        ),
+        &[
+            ("https://example.com/".to_owned(), false),
+            ("https://example.com/feed.xml".to_owned(), false),
+            ("https://example.com/posts/hello/".to_owned(), false),
+            ("https://example.com/episode.mp3".to_owned(), false),
+        ]
+    );
+
+    assert_eq!(
+        urls(
+            r#"<feed xmlns="http://www.w3.org/2005/Atom">
+              <link href="/" />
+              <entry><link rel="alternate" href="/posts/hello/"></link></entry>
+            </feed>"#
+        ),
+        &[("/".to_owned(), false), ("/posts/hello/".to_owned(), false)]
+    );
+}
+
+#[test]
+fn test_xml_urls_other() {
+    assert_eq!(urls("<config><link>/foo</link></config>"), &[]);
+    assert_eq!(urls("<config><link>/foo</config"), &[]);
+    assert_eq!(urls("not xml at all <<"), &[]);
+}
+
+#[test]
+fn test_xml_urls_malformed() {
+    let mut urls = Vec::new();
+    assert!(xml_urls(
+        b"<rss><channel><link>/</link><item><link>/posts/?a=1&b=2</link></item></channel></rss>",
+        &mut urls
+    )
+    .is_err());
+    assert_eq!(
+        urls,
+        &[FeedUrl {
+            url: "/".to_owned(),
+            is_sitemap_entry: false
+        }]
+    );
+}
+
+#[test]
+fn test_robots_txt_urls() {
+    assert_eq!(
+        robots_txt_urls(
+            "User-agent: *\nDisallow: /private/\n# Sitemap: /old.xml\nSITEMAP: https://example.com/sitemap.xml # main\n"
+        )
+        .collect::<Vec<_>>(),
+        &["https://example.com/sitemap.xml"]
+    );
+}
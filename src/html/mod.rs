@@ -1,29 +1,55 @@
+mod charset;
+mod compression;
+mod css;
+mod epub;
+mod feeds;
 mod parser;
+mod pdf;
+mod profile;
+mod redirects;
+mod sniff;
+mod text_fragments;
+mod unicode;
 
 use std::borrow::Cow;
+use std::cell::Cell;
 use std::fmt;
 use std::fs;
 use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::str;
 use std::sync::Arc;
 
-use anyhow::Error;
+use anyhow::{anyhow, Error};
 use bumpalo::collections::String as BumpString;
 use bumpalo::collections::Vec as BumpVec;
 use html5gum::{IoReader, Tokenizer};
 
-use crate::paragraph::ParagraphWalker;
+use crate::paragraph::{NoopParagraphWalker, ParagraphWalker};
+
+use compression::Compression;
+pub use epub::CONTAINER_HREF as EPUB_CONTAINER_HREF;
+#[cfg(test)]
+pub use pdf::build_test_pdf;
+pub use pdf::is_checked_parameter as is_checked_pdf_parameter;
+pub use profile::ServerProfile;
+pub use redirects::pattern_matches;
+pub use sniff::sniff_html;
+pub use text_fragments::{directives_match, split_directives};
+pub use unicode::UnicodeForm;
 
 #[cfg(test)]
 use pretty_assertions::assert_eq;
 
+/// Resolve `path` relative to `base`. With `collapse_index`, `foo/`, `foo/index.html` and `foo`
+/// all become `foo`. Otherwise trailing slashes and index files are preserved.
 #[inline]
-fn push_and_canonicalize(base: &mut BumpString, path: &str) {
+fn push_and_canonicalize(base: &mut BumpString, path: &str, collapse_index: bool) {
     if path.starts_with('/') {
         base.clear();
     } else if path.is_empty() {
-        if base.ends_with('/') {
+        if collapse_index && base.ends_with('/') {
             base.truncate(base.len() - 1);
         }
         return;
@@ -32,10 +58,13 @@ fn push_and_canonicalize(base: &mut BumpString, path: &str) {
     }
 
     let num_slashes = path.matches('/').count();
+    let mut is_dir = false;
 
     for (i, component) in path.split('/').enumerate() {
+        is_dir = matches!(component, "" | "." | "..");
+
         match component {
-            "index.html" | "index.htm" if i == num_slashes => {}
+            "index.html" | "index.htm" if collapse_index && i == num_slashes => {}
             "" | "." => {}
             ".." => {
                 base.truncate(base.rfind('/').unwrap_or(0));
@@ -48,6 +77,10 @@ fn push_and_canonicalize(base: &mut BumpString, path: &str) {
             }
         }
     }
+
+    if !collapse_index && is_dir && !base.is_empty() {
+        base.push('/');
+    }
 }
 
 #[cfg(test)]
@@ -58,7 +91,7 @@ mod test_push_and_canonicalize {
     fn push_and_canonicalize(base: &mut String, path: &str) {
         let arena = bumpalo::Bump::new();
         let mut base2 = BumpString::from_str_in(&*base, &arena);
-        push_and_canonicalize_impl(&mut base2, path);
+        push_and_canonicalize_impl(&mut base2, path, true);
         *base = base2.as_str().to_owned();
     }
 
@@ -106,6 +139,24 @@ mod test_push_and_canonicalize {
         assert_eq!(base, "foo");
     }
 
+    #[test]
+    fn exact() {
+        let arena = bumpalo::Bump::new();
+        let push_exact = |base: &str, path: &str| {
+            let mut base = BumpString::from_str_in(base, &arena);
+            push_and_canonicalize_impl(&mut base, path, false);
+            base.as_str().to_owned()
+        };
+
+        assert_eq!(push_exact("foo/bar.html", "index.html"), "foo/index.html");
+        assert_eq!(push_exact("foo/bar.html", "baz/"), "foo/baz/");
+        assert_eq!(push_exact("foo/bar.html", "."), "foo/");
+        assert_eq!(push_exact("foo/bar.html", ".."), "");
+        assert_eq!(push_exact("foo/bar/index.html", "../"), "foo/");
+        assert_eq!(push_exact("foo/index.html", ""), "foo/index.html");
+        assert_eq!(push_exact("foo/index.html", "/"), "");
+    }
+
     #[test]
     fn index_html_middle() {
         let mut base = String::from("foo/bar.html");
@@ -126,7 +177,7 @@ fn try_percent_decode(input: &str) -> Cow<'_, str> {
 pub struct Href<'a>(pub &'a str);
 
 impl<'a> Href<'a> {
-    pub fn without_anchor(&self) -> Href<'_> {
+    pub fn without_anchor(&self) -> Href<'a> {
         let mut s = self.0;
 
         if let Some(i) = s.find('#') {
@@ -155,18 +206,271 @@ pub struct DefinedLink<'a> {
     pub href: Href<'a>,
 }
 
+/// A redirect from one href to another, either by a redirect rule or by a
+/// `<meta http-equiv="refresh">` stub page.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Redirect<'a> {
+    pub source: Href<'a>,
+    pub destination: Href<'a>,
+    pub path: Arc<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub enum RelationKind {
+    /// The source is a sitemap that lists the target as a page of the site.
+    SitemapEntry,
+    /// `<link rel="canonical">`
+    Canonical,
+    /// `<link rel="alternate" hreflang="...">`
+    Alternate,
+    /// `rel="prev"` on `<link>` or `<a>`.
+    Prev,
+    /// `rel="next"` on `<link>` or `<a>`.
+    Next,
+}
+
+/// A link from one document to another that means more than "this href must exist", and is
+/// checked against the rest of the site once all documents have been read.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Relation<'a> {
+    pub kind: RelationKind,
+    pub source: Href<'a>,
+    pub target: Href<'a>,
+    /// The language of an `Alternate`.
+    pub hreflang: Option<&'a str>,
+    pub path: Arc<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub enum DiagnosticKind {
+    /// A `<base href>` that came too late or could not be honored.
+    BadBase,
+    /// A root-relative link that does not start with `--path-prefix`.
+    MissingPathPrefix,
+    /// Redirects that never reach a final destination.
+    RedirectLoop,
+    /// Redirects that take more hops than `--max-redirect-chain` to reach their destination.
+    RedirectChain,
+    /// Canonical links to pages that are not canonical themselves.
+    BadCanonical,
+    /// hreflang alternates that don't link back.
+    BadHreflang,
+    /// prev/next links that don't form a chain.
+    BadPagination,
+    /// IDREF attributes that don't match any id in the document, see `--check-idrefs`.
+    BadIdref,
+    /// Anchors defined by more than one element of a document, see `--check-duplicate-anchors`.
+    DuplicateAnchor,
+    /// Links that are only served because the server ignores case, see `--check-case`.
+    CaseMismatch,
+    /// Links that only match a file in another Unicode normalization form, see
+    /// `--unicode-normalization`.
+    UnicodeMismatch,
+    /// Attribute values and file names that are not valid UTF-8 and could not be checked.
+    InvalidUtf8,
+    /// Files such as PDFs, feeds and redirect configs that could not be parsed, so that their links
+    /// and anchors are not checked.
+    UnreadableFile,
+    /// Spine and navigation entries of an EPUB package that don't refer to a content document,
+    /// see `--epub`.
+    BadPackage,
+    /// HTML pages that no sitemap lists, see `--check-sitemap`.
+    MissingFromSitemap,
+    /// Symlinks to one of their own parent folders, which are not followed, see
+    /// `--follow-symlinks`.
+    SymlinkLoop,
+}
+
+impl DiagnosticKind {
+    /// Errors fail the run like broken links do, everything else is a warning.
+    pub fn is_error(&self) -> bool {
+        match self {
+            DiagnosticKind::BadBase => false,
+            DiagnosticKind::MissingPathPrefix => true,
+            DiagnosticKind::RedirectLoop => true,
+            DiagnosticKind::RedirectChain => false,
+            DiagnosticKind::BadCanonical => false,
+            DiagnosticKind::BadHreflang => false,
+            DiagnosticKind::BadPagination => false,
+            DiagnosticKind::BadIdref => false,
+            DiagnosticKind::DuplicateAnchor => false,
+            DiagnosticKind::CaseMismatch => false,
+            DiagnosticKind::UnicodeMismatch => false,
+            DiagnosticKind::InvalidUtf8 => true,
+            DiagnosticKind::UnreadableFile => false,
+            DiagnosticKind::BadPackage => true,
+            DiagnosticKind::MissingFromSitemap => false,
+            DiagnosticKind::SymlinkLoop => false,
+        }
+    }
+
+    /// Whether the run fails because of this diagnostic. Warnings of checks that always run, such
+    /// as `BadBase`, are only printed, so that sites that passed before they existed still pass.
+    pub fn fails_run(&self) -> bool {
+        self.is_error()
+            || !matches!(
+                self,
+                DiagnosticKind::BadBase | DiagnosticKind::UnreadableFile
+            )
+    }
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DiagnosticKind::BadBase => "bad base hrefs",
+            DiagnosticKind::MissingPathPrefix => "links without path prefix",
+            DiagnosticKind::RedirectLoop => "redirect loops",
+            DiagnosticKind::RedirectChain => "long redirect chains",
+            DiagnosticKind::BadCanonical => "bad canonical links",
+            DiagnosticKind::BadHreflang => "non-reciprocal hreflang alternates",
+            DiagnosticKind::BadPagination => "inconsistent prev/next links",
+            DiagnosticKind::BadIdref => "bad id references",
+            DiagnosticKind::DuplicateAnchor => "duplicate anchors",
+            DiagnosticKind::CaseMismatch => "links differing in case",
+            DiagnosticKind::UnicodeMismatch => "links differing in Unicode normalization",
+            DiagnosticKind::InvalidUtf8 => "strings with invalid UTF-8",
+            DiagnosticKind::UnreadableFile => "unreadable files",
+            DiagnosticKind::BadPackage => "EPUB package problems",
+            DiagnosticKind::MissingFromSitemap => "pages missing from sitemap",
+            DiagnosticKind::SymlinkLoop => "symlink loops",
+        }
+        .fmt(fmt)
+    }
+}
+
+/// A problem with a document that is not a broken link, but is reported alongside them.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub path: Arc<PathBuf>,
+    pub message: String,
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub enum Link<'a, P> {
     Uses(UsedLink<'a, P>),
     Defines(DefinedLink<'a>),
+    /// Like `Defines`, for the href of an HTML document.
+    DefinesPage(DefinedLink<'a>),
+    /// Defines every href matched by a redirect source pattern such as `blog/*`, see
+    /// `pattern_matches`.
+    DefinesPattern(DefinedLink<'a>),
+    Redirects(Redirect<'a>),
+    Relates(Relation<'a>),
+    Reports(Diagnostic),
 }
 
 impl<'a, P> Link<'a, P> {
     pub fn into_paragraph(self) -> Option<P> {
         match self {
             Link::Uses(UsedLink { paragraph, .. }) => paragraph,
-            Link::Defines(_) => None,
+            _ => None,
+        }
+    }
+}
+
+/// What to check in documents besides plain links.
+#[derive(Debug, Default)]
+pub struct CheckOptions {
+    /// Keep anchors of used links and define anchors for ids, see `--check-anchors`.
+    pub check_anchors: bool,
+    /// Report IDREF attributes such as `<label for>` that don't match an id in the same document.
+    pub check_idrefs: bool,
+    /// Report anchors that are defined more than once in a document, see
+    /// `--check-duplicate-anchors`.
+    pub check_duplicate_anchors: bool,
+    /// Define anchors for the pages and named destinations of PDF files, see
+    /// `--check-pdf-anchors`.
+    pub check_pdf_anchors: bool,
+    /// Read XHTML files as HTML and check the package files of EPUB publications, see `--epub`.
+    pub epub: bool,
+    /// Extensions of files that are read as HTML besides `html` and `htm`, see
+    /// `--html-extension`.
+    pub html_extensions: Vec<String>,
+    /// Read files without an extension as HTML if they start like an HTML document, see
+    /// `--sniff-html`.
+    pub sniff_html: bool,
+    /// Read symlinked files and folders, see `--follow-symlinks`.
+    pub follow_symlinks: bool,
+    /// Define the hrefs of symlinks to files that were read already instead of reading them
+    /// again, see `--alias-symlinks`.
+    pub alias_symlinks: bool,
+}
+
+/// Describes how the checked folder is served, i.e. which URLs point into it.
+#[derive(Debug, Default)]
+pub struct ServerConfig {
+    /// URLs under which the site root is served, such as `https://example.com`. Absolute links
+    /// starting with any of them are checked like root-relative links.
+    pub site_urls: Vec<String>,
+    /// URL path under which the site root is served, such as `/docs`. Without leading or trailing
+    /// slash.
+    pub path_prefix: Option<String>,
+    /// How the server maps URLs to files.
+    pub profile: ServerProfile,
+}
+
+/// Where an href found in a document points to, see `ServerConfig::site_href`.
+#[derive(Debug, Eq, PartialEq)]
+pub enum SiteHref<'h> {
+    /// A link into the site that can be joined with the document's href.
+    Internal(Cow<'h, str>),
+    /// A link to another site, or a `mailto:` link etc.
+    External,
+    /// A root-relative link that does not start with the path prefix, and therefore points
+    /// outside of the site.
+    MissingPathPrefix,
+}
+
+impl ServerConfig {
+    /// Map an href found in a document to something that can be joined with the document's href.
+    pub fn site_href<'h>(&self, href: &'h str) -> SiteHref<'h> {
+        let mut is_absolute = false;
+        let mut path = Cow::Borrowed(href);
+
+        for site_url in &self.site_urls {
+            let rest = match href.get(..site_url.len()) {
+                Some(prefix) if prefix.eq_ignore_ascii_case(site_url) => &href[site_url.len()..],
+                _ => continue,
+            };
+
+            if rest.is_empty() {
+                path = Cow::Borrowed("/");
+            } else if rest.starts_with('/') {
+                path = Cow::Borrowed(rest);
+            } else if rest.starts_with(&['?', '#'][..]) {
+                path = Cow::Owned(format!("/{}", rest));
+            } else {
+                continue;
+            }
+
+            is_absolute = true;
+            break;
+        }
+
+        if !is_absolute && parser::is_bad_schema(href.as_bytes()) {
+            return SiteHref::External;
+        }
+
+        if let Some(ref path_prefix) = self.path_prefix {
+            if path.starts_with('/') {
+                let rest = &path[1..];
+                match rest.strip_prefix(path_prefix.as_str()) {
+                    Some("") => path = Cow::Borrowed("/"),
+                    Some(rest) if rest.starts_with('/') => path = Cow::Owned(rest.to_owned()),
+                    Some(rest) if rest.starts_with(&['?', '#'][..]) => {
+                        path = Cow::Owned(format!("/{}", rest))
+                    }
+                    // Absolute URLs are written deliberately, so assume they point to some other
+                    // part of the domain that is not served from this folder.
+                    _ if is_absolute => return SiteHref::External,
+                    _ => return SiteHref::MissingPathPrefix,
+                }
+            }
         }
+
+        SiteHref::Internal(path)
     }
 }
 
@@ -186,75 +490,261 @@ impl DocumentBuffers {
     }
 }
 
+/// Turn a path relative to the base path into an href.
+fn path_to_href(path: &Path) -> Result<String, Error> {
+    let mut href = path
+        .to_str()
+        .ok_or_else(|| anyhow!("file name is not valid UTF-8"))?
+        .to_owned();
+
+    if cfg!(windows) {
+        unsafe {
+            // safety: we replace ascii bytes only
+            // safety: href is an exclusive reference or owned string
+            let href = href.as_bytes_mut();
+            for b in href.iter_mut() {
+                if *b == b'\\' {
+                    *b = b'/';
+                }
+            }
+        }
+    }
+
+    Ok(href)
+}
+
 pub struct Document {
     pub path: Arc<PathBuf>,
     href: String,
     pub is_index_html: bool,
+    profile: ServerProfile,
+    /// Set for precompressed files such as `page.html.gz`, whose href is that of `page.html`.
+    compression: Option<Compression>,
+    /// The href of the precompressed file itself, which is linkable too.
+    compressed_href: Option<String>,
+    /// Set for precompressed files that are not read, see `Document::find_variants`.
+    is_shadowed: bool,
 }
 
 impl Document {
-    pub fn new(base_path: &Path, path: &Path) -> Self {
-        let mut href_path = path
+    pub fn new(base_path: &Path, path: &Path) -> Result<Self, Error> {
+        Document::with_profile(base_path, path, ServerProfile::Default)
+    }
+
+    /// Create a document whose href is defined according to the given server profile. See
+    /// `ServerProfile::collapses_index`.
+    ///
+    /// Fails for file names that are not valid UTF-8, as no link could point to them.
+    pub fn with_profile(
+        base_path: &Path,
+        path: &Path,
+        profile: ServerProfile,
+    ) -> Result<Self, Error> {
+        let file_path = path
             .strip_prefix(base_path)
             .expect("base_path is not a base of path");
 
+        let compression = Compression::from_path(file_path);
+        let uncompressed_path = compression.map(|_| file_path.with_extension(""));
+        let mut href_path = uncompressed_path.as_deref().unwrap_or(file_path);
+
         let is_index_html = href_path.ends_with("index.html") || href_path.ends_with("index.htm");
 
-        if is_index_html {
+        if is_index_html && profile.collapses_index() {
             href_path = href_path.parent().unwrap_or(href_path);
         }
 
-        let mut href = href_path
-            .to_str()
-            .expect("Invalid unicode in path")
-            .to_owned();
-
-        if cfg!(windows) {
-            unsafe {
-                // safety: we replace ascii bytes only
-                // safety: href is an exclusive reference or owned string
-                let href = href.as_bytes_mut();
-                for b in href.iter_mut() {
-                    if *b == b'\\' {
-                        *b = b'/';
-                    }
-                }
-            }
-        }
+        let compressed_href = match compression {
+            Some(_) => Some(path_to_href(file_path)?),
+            None => None,
+        };
 
-        Document {
+        Ok(Document {
             path: Arc::new(path.to_owned()),
-            href,
+            href: path_to_href(href_path)?,
             is_index_html,
-        }
+            profile,
+            compression,
+            compressed_href,
+            is_shadowed: false,
+        })
     }
 
     pub fn href(&self) -> Href<'_> {
         Href(&self.href)
     }
 
+    /// The href of a precompressed file as it is named on disk, such as `page.html.gz`.
+    pub fn compressed_href(&self) -> Option<Href<'_>> {
+        self.compressed_href.as_deref().map(Href)
+    }
+
+    /// The extension of the file, or of the file it decompresses to.
+    pub fn extension(&self) -> Option<&str> {
+        let name = match self.compression {
+            Some(_) => Path::new(self.path.file_stem()?),
+            None => self.path.as_path(),
+        };
+        name.extension()?.to_str()
+    }
+
+    /// Look for the other variants of a precompressed file among the files of the site, for which
+    /// `exists` returns true. See `Compression::is_shadowed`.
+    pub fn find_variants(&mut self, exists: impl Fn(&Path) -> bool) {
+        self.is_shadowed = match self.compression {
+            Some(compression) => compression.is_shadowed(&self.path, exists),
+            None => false,
+        };
+    }
+
+    /// Whether this is a precompressed file that servers serve another variant of instead, such
+    /// as its uncompressed original. Only that variant is read.
+    pub fn is_shadowed(&self) -> bool {
+        self.is_shadowed
+    }
+
+    /// Decompress the contents of a precompressed file, or pass through those of any other file.
+    pub fn decompress<'r, R: Read + 'r>(&self, read: R) -> Box<dyn Read + 'r> {
+        match self.compression {
+            Some(compression) => compression.decompress(read),
+            None => Box::new(read),
+        }
+    }
+
+    /// The href that relative links in this document are resolved against, in the form that
+    /// `push_and_canonicalize` expects.
+    fn base_href<'b>(&self, arena: &'b bumpalo::Bump) -> BumpString<'b> {
+        let mut href = BumpString::from_str_in(&self.href, arena);
+        if self.is_index_html && self.profile.collapses_index() {
+            href.push('/');
+        }
+        href
+    }
+
+    /// Resolve the value of a `<base href>` to the directory it points to, relative to the site
+    /// root and with a trailing slash. Returns `None` if the base points outside of the site.
+    fn resolve_base<'b>(&self, arena: &'b bumpalo::Bump, base_href: &str) -> Option<&'b str> {
+        let qs_start = base_href.find(&['?', '#'][..]).unwrap_or(base_href.len());
+        let path = try_percent_decode(&base_href[..qs_start]);
+        // Only the directory of the base URL matters, `<base href=/docs/index.html>` is the same
+        // as `<base href=/docs/>`.
+        let dir = match path.rfind('/') {
+            Some(i) => &path[..=i],
+            None => "./",
+        };
+
+        let mut href = self.base_href(arena);
+
+        // Check that ".." does not escape the site root. push_and_canonicalize would silently
+        // clamp those paths to the root instead.
+        let mut depth = if dir.starts_with('/') {
+            0
+        } else {
+            match href.rfind('/') {
+                Some(i) if i > 0 => href[..i].split('/').count(),
+                _ => 0,
+            }
+        };
+
+        for component in dir.split('/') {
+            match component {
+                "" | "." => {}
+                ".." if depth == 0 => return None,
+                ".." => depth -= 1,
+                _ => depth += 1,
+            }
+        }
+
+        push_and_canonicalize(&mut href, dir, true);
+        if !href.is_empty() {
+            href.push('/');
+        }
+
+        Some(href.into_bump_str())
+    }
+
     fn join<'b>(
         &self,
         arena: &'b bumpalo::Bump,
         preserve_anchor: bool,
         rel_href: &str,
     ) -> Href<'b> {
-        let qs_start = rel_href
-            .find(&['?', '#'][..])
-            .unwrap_or_else(|| rel_href.len());
-        let anchor_start = rel_href.find('#').unwrap_or_else(|| rel_href.len());
+        self.join_with_base(arena, None, preserve_anchor, rel_href)
+    }
 
-        let mut href = BumpString::from_str_in(&self.href, arena);
-        if self.is_index_html {
-            href.push('/');
+    /// Resolve an href found in this document and push the resulting link into `link_buf`. Returns
+    /// the resolved href if it points into the site.
+    fn push_used_link<'a, 'l, P>(
+        &self,
+        arena: &'a bumpalo::Bump,
+        server_config: &ServerConfig,
+        base: Option<&str>,
+        preserve_anchor: bool,
+        link_buf: &mut BumpVec<'a, Link<'l, P>>,
+        href: &str,
+    ) -> Option<Href<'a>>
+    where
+        'a: 'l,
+    {
+        match server_config.site_href(href) {
+            SiteHref::Internal(href) => {
+                let href = self.join_with_base(arena, base, preserve_anchor, &href);
+                link_buf.push(Link::Uses(UsedLink {
+                    href: href.clone(),
+                    path: self.path.clone(),
+                    paragraph: None,
+                }));
+                return Some(href);
+            }
+            SiteHref::External => {}
+            SiteHref::MissingPathPrefix => link_buf.push(Link::Reports(Diagnostic {
+                kind: DiagnosticKind::MissingPathPrefix,
+                path: self.path.clone(),
+                message: format!(
+                    "link {} does not start with path prefix /{}",
+                    href,
+                    server_config.path_prefix.as_deref().unwrap_or_default()
+                ),
+            })),
         }
 
-        push_and_canonicalize(&mut href, &try_percent_decode(&rel_href[..qs_start]));
+        None
+    }
+
+    /// Like `join`, but resolves `rel_href` against `base` (as returned by `resolve_base`)
+    /// instead of the document itself if given.
+    fn join_with_base<'b>(
+        &self,
+        arena: &'b bumpalo::Bump,
+        base: Option<&str>,
+        preserve_anchor: bool,
+        rel_href: &str,
+    ) -> Href<'b> {
+        let qs_start = rel_href.find(&['?', '#'][..]).unwrap_or(rel_href.len());
+        let anchor_start = rel_href.find('#').unwrap_or(rel_href.len());
+
+        let mut href = match base {
+            Some(base) => BumpString::from_str_in(base, arena),
+            None => self.base_href(arena),
+        };
+
+        push_and_canonicalize(
+            &mut href,
+            &try_percent_decode(&rel_href[..qs_start]),
+            self.profile.collapses_index(),
+        );
 
         if preserve_anchor {
             let anchor = &rel_href[anchor_start..];
             if anchor.len() > 1 {
-                href.push_str(&try_percent_decode(&anchor));
+                // The parts of text directives are percent-encoded individually, so that they can
+                // contain commas. Leave them alone until they are parsed.
+                let (anchor, directives) = split_directives(anchor);
+                href.push_str(&try_percent_decode(anchor));
+                if let Some(directives) = directives {
+                    href.push_str(":~:");
+                    href.push_str(directives);
+                }
             }
         }
 
@@ -264,7 +754,8 @@ impl Document {
     pub fn links<'b, 'l, P: ParagraphWalker>(
         &self,
         doc_buf: &'b mut DocumentBuffers,
-        check_anchors: bool,
+        server_config: &ServerConfig,
+        options: &CheckOptions,
         get_paragraphs: bool,
     ) -> Result<impl Iterator<Item = Link<'l, P::Paragraph>>, Error>
     where
@@ -273,44 +764,350 @@ impl Document {
         self.links_from_read::<_, P>(
             doc_buf,
             fs::File::open(&*self.path)?,
-            check_anchors,
+            server_config,
+            options,
             get_paragraphs,
         )
     }
 
-    fn links_from_read<'b, 'l, R: Read, P: ParagraphWalker>(
+    /// Extract all links from a stylesheet. Those are resolved relative to the stylesheet itself.
+    pub fn css_links_from_read<'b, 'l, R: Read, P: ParagraphWalker>(
         &self,
         doc_buf: &'b mut DocumentBuffers,
-        read: R,
+        mut read: R,
+        server_config: &ServerConfig,
+        check_anchors: bool,
+    ) -> Result<impl Iterator<Item = Link<'l, P::Paragraph>>, Error>
+    where
+        'b: 'l,
+    {
+        let mut css = Vec::new();
+        read.read_to_end(&mut css)?;
+
+        let mut link_buf = BumpVec::new_in(&doc_buf.arena);
+        css::push_css_links(
+            self,
+            &doc_buf.arena,
+            server_config,
+            None,
+            check_anchors,
+            &mut link_buf,
+            &css,
+        );
+
+        Ok(link_buf.into_iter())
+    }
+
+    /// Extract the redirect rules from a `_redirects`, `netlify.toml` or `vercel.json` file at the
+    /// site root. Sources of redirects are defined links, destinations are used links.
+    pub fn redirect_links_from_read<'b, 'l, R: Read, P: ParagraphWalker>(
+        &self,
+        doc_buf: &'b mut DocumentBuffers,
+        mut read: R,
+        server_config: &ServerConfig,
+        check_anchors: bool,
+    ) -> Result<impl Iterator<Item = Link<'l, P::Paragraph>>, Error>
+    where
+        'b: 'l,
+    {
+        let mut contents = String::new();
+        read.read_to_string(&mut contents)?;
+
+        let arena = &doc_buf.arena;
+        let mut link_buf = BumpVec::new_in(arena);
+
+        let rules = match redirects::parse(&self.href, &contents) {
+            Ok(rules) => rules,
+            // Links that rely on the redirects of a broken config are reported as broken.
+            Err(error) => {
+                link_buf.push(Link::Reports(Diagnostic {
+                    kind: DiagnosticKind::UnreadableFile,
+                    path: self.path.clone(),
+                    message: format!("cannot read redirects, ignoring them: {}", error),
+                }));
+                Vec::new()
+            }
+        };
+
+        for rule in rules {
+            let mut source = None;
+
+            if rule.defines_source() {
+                // Sources outside of the site (e.g. redirects for other domains) are irrelevant.
+                if let SiteHref::Internal(source_path) = server_config.site_href(&rule.source) {
+                    let href = self.join(arena, false, &source_path);
+                    if redirects::is_pattern(&source_path) {
+                        link_buf.push(Link::DefinesPattern(DefinedLink { href }));
+                    } else {
+                        link_buf.push(Link::Defines(DefinedLink { href: href.clone() }));
+                        source = Some(href);
+                    }
+                }
+            }
+
+            // Destinations that depend on the source can't be checked statically.
+            if redirects::is_pattern(&rule.destination) {
+                continue;
+            }
+
+            let destination = self.push_used_link(
+                arena,
+                server_config,
+                None,
+                check_anchors,
+                &mut link_buf,
+                &rule.destination,
+            );
+
+            if let (Some(source), Some(destination)) = (source, destination) {
+                link_buf.push(Link::Redirects(Redirect {
+                    source,
+                    destination,
+                    path: self.path.clone(),
+                }));
+            }
+        }
+
+        Ok(link_buf.into_iter())
+    }
+
+    /// Define the anchors of a PDF file: its pages and named destinations.
+    pub fn pdf_links_from_read<'b, 'l, R: Read, P: ParagraphWalker>(
+        &self,
+        doc_buf: &'b mut DocumentBuffers,
+        mut read: R,
+    ) -> Result<impl Iterator<Item = Link<'l, P::Paragraph>>, Error>
+    where
+        'b: 'l,
+    {
+        let mut pdf = Vec::new();
+        read.read_to_end(&mut pdf)?;
+
+        let arena = &doc_buf.arena;
+        let mut link_buf = BumpVec::new_in(arena);
+
+        match pdf::pdf_anchors(&pdf) {
+            Ok(anchors) => {
+                for anchor in anchors {
+                    link_buf.push(Link::Defines(DefinedLink {
+                        href: self.join(arena, true, &format!("#{}", anchor)),
+                    }));
+                }
+            }
+            // A corrupt or encrypted PDF only defines no anchors, links to it are still checked.
+            Err(error) => link_buf.push(Link::Reports(Diagnostic {
+                kind: DiagnosticKind::UnreadableFile,
+                path: self.path.clone(),
+                message: format!("cannot read anchors of PDF: {}", error),
+            })),
+        }
+
+        Ok(link_buf.into_iter())
+    }
+
+    /// Extract all URLs from a sitemap, RSS or Atom feed, or from the `Sitemap:` lines of a
+    /// robots.txt.
+    pub fn feed_links_from_read<'b, 'l, R: Read, P: ParagraphWalker>(
+        &self,
+        doc_buf: &'b mut DocumentBuffers,
+        mut read: R,
+        server_config: &ServerConfig,
+        check_anchors: bool,
+    ) -> Result<impl Iterator<Item = Link<'l, P::Paragraph>>, Error>
+    where
+        'b: 'l,
+    {
+        let mut contents = Vec::new();
+        read.read_to_end(&mut contents)?;
+
+        let arena = &doc_buf.arena;
+        let mut link_buf = BumpVec::new_in(arena);
+        let mut urls = Vec::new();
+
+        if self.href == "robots.txt" {
+            urls.extend(
+                feeds::robots_txt_urls(&String::from_utf8_lossy(&contents)).map(|url| {
+                    feeds::FeedUrl {
+                        url: url.to_owned(),
+                        is_sitemap_entry: false,
+                    }
+                }),
+            );
+        } else if let Err(error) = feeds::xml_urls(&contents, &mut urls) {
+            // The URLs before the error are still checked.
+            link_buf.push(Link::Reports(Diagnostic {
+                kind: DiagnosticKind::UnreadableFile,
+                path: self.path.clone(),
+                message: format!("malformed XML, later URLs are not checked: {}", error),
+            }));
+        }
+
+        for url in urls {
+            let target = self.push_used_link(
+                arena,
+                server_config,
+                None,
+                check_anchors,
+                &mut link_buf,
+                &url.url,
+            );
+
+            if let (true, Some(target)) = (url.is_sitemap_entry, target) {
+                link_buf.push(Link::Relates(Relation {
+                    kind: RelationKind::SitemapEntry,
+                    source: Href(arena.alloc_str(&self.href)),
+                    target,
+                    hreflang: None,
+                    path: self.path.clone(),
+                }));
+            }
+        }
+
+        Ok(link_buf.into_iter())
+    }
+
+    /// Extract the links of an EPUB package file: the package documents listed by
+    /// `META-INF/container.xml`, the manifest items of an OPF package document and the entries of
+    /// an NCX table of contents. Spine problems of a package document are reported.
+    pub fn epub_links_from_read<'b, 'l, R: Read, P: ParagraphWalker>(
+        &self,
+        doc_buf: &'b mut DocumentBuffers,
+        mut read: R,
+        server_config: &ServerConfig,
         check_anchors: bool,
+    ) -> Result<impl Iterator<Item = Link<'l, P::Paragraph>>, Error>
+    where
+        'b: 'l,
+    {
+        let mut contents = Vec::new();
+        read.read_to_end(&mut contents)?;
+
+        let arena = &doc_buf.arena;
+        let mut link_buf = BumpVec::new_in(arena);
+
+        let hrefs = if self.href == epub::CONTAINER_HREF {
+            // Relative to the root of the publication, not to the container.
+            epub::container_rootfiles(&contents)?
+                .into_iter()
+                .map(|path| format!("/{}", path))
+                .collect()
+        } else if self.href.ends_with(".opf") {
+            let package = epub::parse_package(&contents)?;
+
+            for problem in package.problems() {
+                link_buf.push(Link::Reports(Diagnostic {
+                    kind: DiagnosticKind::BadPackage,
+                    path: self.path.clone(),
+                    message: problem,
+                }));
+            }
+
+            package.manifest.into_iter().map(|item| item.href).collect()
+        } else {
+            epub::ncx_sources(&contents)?
+        };
+
+        for href in hrefs {
+            self.push_used_link(
+                arena,
+                server_config,
+                None,
+                check_anchors,
+                &mut link_buf,
+                &href,
+            );
+        }
+
+        Ok(link_buf.into_iter())
+    }
+
+    pub fn links_from_read<'b, 'l, R: Read, P: ParagraphWalker>(
+        &self,
+        doc_buf: &'b mut DocumentBuffers,
+        read: R,
+        server_config: &ServerConfig,
+        options: &CheckOptions,
         get_paragraphs: bool,
     ) -> Result<impl Iterator<Item = Link<'l, P::Paragraph>>, Error>
+    where
+        'b: 'l,
+    {
+        Ok(self
+            .tokenize::<_, P>(doc_buf, read, server_config, options, get_paragraphs, false)?
+            .into_iter())
+    }
+
+    /// Read the visible text of this document, normalized for checking text fragments against.
+    pub fn text_from_read<R: Read>(
+        &self,
+        doc_buf: &mut DocumentBuffers,
+        read: R,
+    ) -> Result<String, Error> {
+        self.tokenize::<_, NoopParagraphWalker>(
+            doc_buf,
+            read,
+            &ServerConfig::default(),
+            &CheckOptions::default(),
+            false,
+            true,
+        )?;
+
+        Ok(text_fragments::normalize_text(&String::from_utf8_lossy(
+            doc_buf.parser_buffers.text(),
+        )))
+    }
+
+    fn tokenize<'b, 'l, R: Read, P: ParagraphWalker>(
+        &self,
+        doc_buf: &'b mut DocumentBuffers,
+        read: R,
+        server_config: &ServerConfig,
+        options: &CheckOptions,
+        get_paragraphs: bool,
+        get_text: bool,
+    ) -> Result<BumpVec<'b, Link<'l, P::Paragraph>>, Error>
     where
         'b: 'l,
     {
         let mut link_buf = BumpVec::new_in(&doc_buf.arena);
+        let position = Rc::new(Cell::new(0));
 
         {
             let emitter = parser::HyperlinkEmitter {
                 paragraph_walker: P::new(),
                 arena: &doc_buf.arena,
                 document: self,
+                server_config,
                 link_buf: &mut link_buf,
                 in_paragraph: false,
                 last_paragraph_i: 0,
                 get_paragraphs,
+                get_text,
                 buffers: &mut doc_buf.parser_buffers,
                 current_tag_is_closing: false,
-                check_anchors,
+                check_anchors: options.check_anchors,
+                check_idrefs: options.check_idrefs,
+                check_duplicate_anchors: options.check_duplicate_anchors,
+                base: None,
+                seen_base: false,
+                in_style: false,
+                current_input_is_image: false,
+                current_meta_is_refresh: false,
+                current_href: None,
+                position: position.clone(),
+            };
+            let reader = parser::CountingReader {
+                reader: IoReader::new(charset::decode_read(read)?),
+                position,
             };
-            let reader = Tokenizer::new_with_emitter(IoReader::new(read), emitter);
+            let reader = Tokenizer::new_with_emitter(reader, emitter);
 
             for error in reader {
                 error?;
             }
         }
 
-        Ok(link_buf.into_iter())
+        Ok(link_buf)
     }
 }
 
@@ -319,21 +1116,64 @@ fn test_document_href() {
     let doc = Document::new(
         Path::new("public/"),
         Path::new("public/platforms/python/troubleshooting/index.html"),
-    );
+    )
+    .unwrap();
 
-    assert_eq!(doc.href(), Href("platforms/python/troubleshooting".into()));
+    assert_eq!(doc.href(), Href("platforms/python/troubleshooting"));
 
     let doc = Document::new(
         Path::new("public/"),
         Path::new("public/platforms/python/troubleshooting.html"),
-    );
+    )
+    .unwrap();
+
+    assert_eq!(doc.href(), Href("platforms/python/troubleshooting.html"));
+}
+
+#[test]
+fn test_document_href_profile() {
+    let doc = Document::with_profile(
+        Path::new("public/"),
+        Path::new("public/platforms/python/index.html"),
+        ServerProfile::Strict,
+    )
+    .unwrap();
 
+    assert_eq!(doc.href(), Href("platforms/python/index.html"));
+
+    let arena = bumpalo::Bump::new();
+    assert_eq!(doc.join(&arena, true, "../ruby/"), Href("platforms/ruby/"));
     assert_eq!(
-        doc.href(),
-        Href("platforms/python/troubleshooting.html".into())
+        doc.join(&arena, true, "#foo"),
+        Href("platforms/python/index.html#foo")
     );
 }
 
+#[test]
+fn test_document_href_compressed() {
+    let doc = Document::new(
+        Path::new("public/"),
+        Path::new("public/guide/index.html.gz"),
+    )
+    .unwrap();
+
+    assert_eq!(doc.href(), Href("guide"));
+    assert_eq!(doc.compressed_href(), Some(Href("guide/index.html.gz")));
+    assert_eq!(doc.extension(), Some("html"));
+
+    let doc = Document::new(Path::new("public/"), Path::new("public/style.css.br")).unwrap();
+
+    assert_eq!(doc.href(), Href("style.css"));
+    assert_eq!(doc.compressed_href(), Some(Href("style.css.br")));
+    assert_eq!(doc.extension(), Some("css"));
+
+    let doc = Document::new(Path::new("public/"), Path::new("public/data.gz")).unwrap();
+
+    assert_eq!(doc.href(), Href("data.gz"));
+    assert_eq!(doc.compressed_href(), None);
+    assert_eq!(doc.extension(), Some("gz"));
+}
+
 #[test]
 fn test_html_parsing_malformed_script() {
     use crate::paragraph::ParagraphHasher;
@@ -355,17 +1195,23 @@ fn test_html_parsing_malformed_script() {
         <a href=bar />
     "###;
 
-    let doc = Document::new(Path::new("public/"), Path::new("public/hello.html"));
+    let doc = Document::new(Path::new("public/"), Path::new("public/hello.html")).unwrap();
 
     let mut doc_buf = DocumentBuffers::default();
 
     let links = doc
-        .links_from_read::<_, ParagraphHasher>(&mut doc_buf, html.as_bytes(), false, false)
+        .links_from_read::<_, ParagraphHasher>(
+            &mut doc_buf,
+            html.as_bytes(),
+            &ServerConfig::default(),
+            &CheckOptions::default(),
+            false,
+        )
         .unwrap();
 
     let used_link = |x: &'static str| {
         Link::Uses(UsedLink {
-            href: Href(x.into()),
+            href: Href(x),
             path: doc.path.clone(),
             paragraph: None,
         })
@@ -384,7 +1230,8 @@ fn test_document_links() {
     let doc = Document::new(
         Path::new("public/"),
         Path::new("public/platforms/python/troubleshooting/index.html"),
-    );
+    )
+    .unwrap();
 
     let mut doc_buf = DocumentBuffers::default();
 
@@ -423,14 +1270,15 @@ fn test_document_links() {
     />
     """#
         .as_bytes(),
-        false,
+        &ServerConfig::default(),
+        &CheckOptions::default(),
         false,
     )
     .unwrap();
 
     let used_link = |x: &'static str| {
         Link::Uses(UsedLink {
-            href: Href(x.into()),
+            href: Href(x),
             path: doc.path.clone(),
             paragraph: None,
         })
@@ -463,28 +1311,29 @@ fn test_document_join_index_html() {
     let doc = Document::new(
         Path::new("public/"),
         Path::new("public/platforms/python/troubleshooting/index.html"),
-    );
+    )
+    .unwrap();
 
     assert_eq!(
         doc.join(&arena, false, "../../ruby#foo"),
-        Href("platforms/ruby".into())
+        Href("platforms/ruby")
     );
     assert_eq!(
         doc.join(&arena, true, "../../ruby#foo"),
-        Href("platforms/ruby#foo".into())
+        Href("platforms/ruby#foo")
     );
     assert_eq!(
         doc.join(&arena, true, "../../ruby?bar=1#foo"),
-        Href("platforms/ruby#foo".into())
+        Href("platforms/ruby#foo")
     );
 
     assert_eq!(
         doc.join(&arena, false, "/platforms/ruby"),
-        Href("platforms/ruby".into())
+        Href("platforms/ruby")
     );
     assert_eq!(
         doc.join(&arena, true, "/platforms/ruby?bar=1#foo"),
-        Href("platforms/ruby#foo".into())
+        Href("platforms/ruby#foo")
     );
 }
 
@@ -495,35 +1344,878 @@ fn test_document_join_bare_html() {
     let doc = Document::new(
         Path::new("public/"),
         Path::new("public/platforms/python/troubleshooting.html"),
-    );
+    )
+    .unwrap();
 
     assert_eq!(
         doc.join(&arena, false, "../ruby#foo"),
-        Href("platforms/ruby".into())
+        Href("platforms/ruby")
     );
     assert_eq!(
         doc.join(&arena, true, "../ruby#foo"),
-        Href("platforms/ruby#foo".into())
+        Href("platforms/ruby#foo")
     );
     assert_eq!(
         doc.join(&arena, true, "../ruby?bar=1#foo"),
-        Href("platforms/ruby#foo".into())
+        Href("platforms/ruby#foo")
     );
 
     assert_eq!(
         doc.join(&arena, false, "/platforms/ruby"),
-        Href("platforms/ruby".into())
+        Href("platforms/ruby")
     );
     assert_eq!(
         doc.join(&arena, true, "/platforms/ruby?bar=1#foo"),
-        Href("platforms/ruby#foo".into())
+        Href("platforms/ruby#foo")
     );
     assert_eq!(
         doc.join(&arena, false, "/locations/troms%C3%B8"),
-        Href("locations/tromsø".into())
+        Href("locations/tromsø")
     );
     assert_eq!(
         doc.join(&arena, true, "/locations/oslo#gr%C3%BCnerl%C3%B8kka"),
-        Href("locations/oslo#grünerløkka".into())
+        Href("locations/oslo#grünerløkka")
+    );
+}
+
+#[test]
+fn test_document_links_base() {
+    use crate::paragraph::ParagraphHasher;
+
+    let doc = Document::new(Path::new("public/"), Path::new("public/guide/intro.html")).unwrap();
+
+    let mut doc_buf = DocumentBuffers::default();
+
+    let links = doc
+        .links_from_read::<_, ParagraphHasher>(
+            &mut doc_buf,
+            r##"
+            <head><base href="/docs/index.html"></head>
+            <a href="setup.html" />
+            <a href="../about/" />
+            <a href="#top" />
+            <a href="/absolute.html" />
+            "##
+            .as_bytes(),
+            &ServerConfig::default(),
+            &CheckOptions {
+                check_anchors: true,
+                ..Default::default()
+            },
+            false,
+        )
+        .unwrap();
+
+    let used_link = |x: &'static str| {
+        Link::Uses(UsedLink {
+            href: Href(x),
+            path: doc.path.clone(),
+            paragraph: None,
+        })
+    };
+
+    assert_eq!(
+        links.collect::<Vec<_>>(),
+        &[
+            used_link("docs/setup.html"),
+            used_link("about"),
+            used_link("docs#top"),
+            used_link("absolute.html"),
+        ]
+    );
+}
+
+#[test]
+fn test_document_links_bad_base() {
+    use crate::paragraph::ParagraphHasher;
+
+    let doc = Document::new(Path::new("public/"), Path::new("public/guide/intro.html")).unwrap();
+
+    let mut doc_buf = DocumentBuffers::default();
+
+    let links = doc
+        .links_from_read::<_, ParagraphHasher>(
+            &mut doc_buf,
+            r##"
+            <a href="setup.html" />
+            <base href="../../">
+            <a href="other.html" />
+            "##
+            .as_bytes(),
+            &ServerConfig::default(),
+            &CheckOptions::default(),
+            false,
+        )
+        .unwrap();
+
+    let warning = |message: &'static str| {
+        Link::Reports(Diagnostic {
+            kind: DiagnosticKind::BadBase,
+            path: doc.path.clone(),
+            message: message.to_owned(),
+        })
+    };
+
+    assert_eq!(
+        links.collect::<Vec<_>>(),
+        &[
+            Link::Uses(UsedLink {
+                href: Href("guide/setup.html"),
+                path: doc.path.clone(),
+                paragraph: None,
+            }),
+            warning("<base href=\"../../\"> appears after links, those were resolved without it"),
+            warning("<base href=\"../../\"> points outside of the site, ignoring it"),
+            Link::Uses(UsedLink {
+                href: Href("guide/other.html"),
+                path: doc.path.clone(),
+                paragraph: None,
+            }),
+        ]
+    );
+}
+
+#[test]
+fn test_document_resolve_base() {
+    let arena = bumpalo::Bump::new();
+
+    let doc = Document::new(Path::new("public/"), Path::new("public/guide/index.html")).unwrap();
+
+    assert_eq!(doc.resolve_base(&arena, "/docs/"), Some("docs/"));
+    assert_eq!(
+        doc.resolve_base(&arena, "/docs/page.html?x=1"),
+        Some("docs/")
+    );
+    assert_eq!(doc.resolve_base(&arena, "/"), Some(""));
+    assert_eq!(doc.resolve_base(&arena, ""), Some("guide/"));
+    assert_eq!(doc.resolve_base(&arena, "sub/"), Some("guide/sub/"));
+    assert_eq!(doc.resolve_base(&arena, "../"), Some(""));
+    assert_eq!(doc.resolve_base(&arena, "../../"), None);
+    assert_eq!(doc.resolve_base(&arena, "/../"), None);
+
+    let doc = Document::new(Path::new("public/"), Path::new("public/index.html")).unwrap();
+    assert_eq!(doc.resolve_base(&arena, "./"), Some(""));
+    assert_eq!(doc.resolve_base(&arena, "../"), None);
+}
+
+#[test]
+fn test_document_links_css() {
+    use crate::paragraph::ParagraphHasher;
+
+    let doc = Document::new(Path::new("public/"), Path::new("public/guide/intro.html")).unwrap();
+
+    let mut doc_buf = DocumentBuffers::default();
+
+    let links = doc
+        .links_from_read::<_, ParagraphHasher>(
+            &mut doc_buf,
+            r#"
+            <style>
+                @import "/theme.css";
+                .hero { background-image: url('../img/hero.png'); }
+                .icon { fill: url(#gradient); }
+            </style>
+            <div style="background: url(bg.png), url(data:image/png;base64,AAAA)"></div>
+            "#
+            .as_bytes(),
+            &ServerConfig::default(),
+            &CheckOptions::default(),
+            false,
+        )
+        .unwrap();
+
+    let used_link = |x: &'static str| {
+        Link::Uses(UsedLink {
+            href: Href(x),
+            path: doc.path.clone(),
+            paragraph: None,
+        })
+    };
+
+    assert_eq!(
+        links.collect::<Vec<_>>(),
+        &[
+            used_link("theme.css"),
+            used_link("img/hero.png"),
+            used_link("guide/bg.png"),
+        ]
+    );
+
+    let doc = Document::new(Path::new("public/"), Path::new("public/css/main.css")).unwrap();
+
+    let links = doc
+        .css_links_from_read::<_, ParagraphHasher>(
+            &mut doc_buf,
+            "@font-face { src: url(../fonts/inter.woff2) }".as_bytes(),
+            &ServerConfig::default(),
+            false,
+        )
+        .unwrap();
+
+    assert_eq!(
+        links.collect::<Vec<_>>(),
+        &[Link::Uses(UsedLink {
+            href: Href("fonts/inter.woff2"),
+            path: doc.path.clone(),
+            paragraph: None,
+        })]
+    );
+}
+
+#[test]
+fn test_document_redirect_links() {
+    use crate::paragraph::ParagraphHasher;
+
+    let doc = Document::new(Path::new("public/"), Path::new("public/_redirects")).unwrap();
+
+    let mut doc_buf = DocumentBuffers::default();
+
+    let links = doc
+        .redirect_links_from_read::<_, ParagraphHasher>(
+            &mut doc_buf,
+            r#"
+            /old/index.html   /new/#intro
+            /blog/*           /news/:splat
+            /gone             /404.html  404
+            https://example.com/* https://www.example.com/:splat
+            "#
+            .as_bytes(),
+            &ServerConfig::default(),
+            true,
+        )
+        .unwrap();
+
+    let used_link = |x: &'static str| {
+        Link::Uses(UsedLink {
+            href: Href(x),
+            path: doc.path.clone(),
+            paragraph: None,
+        })
+    };
+
+    assert_eq!(
+        links.collect::<Vec<_>>(),
+        &[
+            Link::Defines(DefinedLink { href: Href("old") }),
+            used_link("new#intro"),
+            Link::Redirects(Redirect {
+                source: Href("old"),
+                destination: Href("new#intro"),
+                path: doc.path.clone(),
+            }),
+            Link::DefinesPattern(DefinedLink {
+                href: Href("blog/*")
+            }),
+            used_link("404.html"),
+        ]
+    );
+}
+
+#[test]
+fn test_document_links_meta_refresh() {
+    use crate::paragraph::ParagraphHasher;
+
+    let doc = Document::new(Path::new("public/"), Path::new("public/old/index.html")).unwrap();
+
+    let mut doc_buf = DocumentBuffers::default();
+
+    let links = doc
+        .links_from_read::<_, ParagraphHasher>(
+            &mut doc_buf,
+            r#"
+            <meta charset="utf-8">
+            <meta content="0; url=../new/#intro" http-equiv="refresh">
+            <meta http-equiv="refresh" content="0; url=https://example.com/">
+            "#
+            .as_bytes(),
+            &ServerConfig::default(),
+            &CheckOptions {
+                check_anchors: true,
+                ..Default::default()
+            },
+            false,
+        )
+        .unwrap();
+
+    assert_eq!(
+        links.collect::<Vec<_>>(),
+        &[
+            Link::Uses(UsedLink {
+                href: Href("new#intro"),
+                path: doc.path.clone(),
+                paragraph: None,
+            }),
+            Link::Redirects(Redirect {
+                source: Href("old"),
+                destination: Href("new#intro"),
+                path: doc.path.clone(),
+            }),
+        ]
+    );
+}
+
+#[test]
+fn test_document_feed_links() {
+    use crate::paragraph::ParagraphHasher;
+
+    let doc = Document::new(Path::new("public/"), Path::new("public/sitemap.xml")).unwrap();
+
+    let mut doc_buf = DocumentBuffers::default();
+
+    let config = ServerConfig {
+        site_urls: vec!["https://example.com".to_owned()],
+        ..Default::default()
+    };
+
+    let links = doc
+        .feed_links_from_read::<_, ParagraphHasher>(
+            &mut doc_buf,
+            r#"<urlset>
+              <url><loc>https://example.com/guide/</loc></url>
+              <url><loc>https://other.example.com/</loc></url>
+            </urlset>"#
+                .as_bytes(),
+            &config,
+            false,
+        )
+        .unwrap();
+
+    assert_eq!(
+        links.collect::<Vec<_>>(),
+        &[
+            Link::Uses(UsedLink {
+                href: Href("guide"),
+                path: doc.path.clone(),
+                paragraph: None,
+            }),
+            Link::Relates(Relation {
+                kind: RelationKind::SitemapEntry,
+                source: Href("sitemap.xml"),
+                target: Href("guide"),
+                hreflang: None,
+                path: doc.path.clone(),
+            }),
+        ]
+    );
+
+    let doc = Document::new(Path::new("public/"), Path::new("public/robots.txt")).unwrap();
+
+    let links = doc
+        .feed_links_from_read::<_, ParagraphHasher>(
+            &mut doc_buf,
+            "User-agent: *\nSitemap: https://example.com/sitemap.xml\n".as_bytes(),
+            &config,
+            false,
+        )
+        .unwrap();
+
+    assert_eq!(
+        links.collect::<Vec<_>>(),
+        &[Link::Uses(UsedLink {
+            href: Href("sitemap.xml"),
+            path: doc.path.clone(),
+            paragraph: None,
+        })]
+    );
+}
+
+#[test]
+fn test_document_links_relations() {
+    use crate::paragraph::ParagraphHasher;
+
+    let doc = Document::new(Path::new("public/"), Path::new("public/blog/page/2.html")).unwrap();
+
+    let mut doc_buf = DocumentBuffers::default();
+
+    let links = doc
+        .links_from_read::<_, ParagraphHasher>(
+            &mut doc_buf,
+            r#"
+            <link rel="canonical" href="/blog/page/2.html">
+            <link hreflang="de" href="/de/blog/page/2.html" rel="alternate">
+            <link rel="alternate stylesheet" href="/dark.css">
+            <a rel="prev nofollow" href="1.html">Previous</a>
+            <a href="3.html" rel="NEXT">Next</a>
+            "#
+            .as_bytes(),
+            &ServerConfig::default(),
+            &CheckOptions::default(),
+            false,
+        )
+        .unwrap()
+        .filter(|link| matches!(link, Link::Relates(_)))
+        .collect::<Vec<_>>();
+
+    let relation = |kind, target: &'static str, hreflang| {
+        Link::Relates(Relation {
+            kind,
+            source: Href("blog/page/2.html"),
+            target: Href(target),
+            hreflang,
+            path: doc.path.clone(),
+        })
+    };
+
+    assert_eq!(
+        links,
+        &[
+            relation(RelationKind::Canonical, "blog/page/2.html", None),
+            relation(RelationKind::Alternate, "de/blog/page/2.html", Some("de")),
+            relation(RelationKind::Prev, "blog/page/1.html", None),
+            relation(RelationKind::Next, "blog/page/3.html", None),
+        ]
+    );
+}
+
+#[test]
+fn test_document_links_idrefs() {
+    use crate::paragraph::ParagraphHasher;
+
+    let doc = Document::new(Path::new("public/"), Path::new("public/form.html")).unwrap();
+
+    let mut doc_buf = DocumentBuffers::default();
+
+    let links = doc
+        .links_from_read::<_, ParagraphHasher>(
+            &mut doc_buf,
+            r##"
+            <label for="email">Email</label>
+            <input id="email" list="domains" aria-describedby="email-hint email-error">
+            <p id="email-hint">
+            <datalist id=domains></datalist>
+            <label for="name">Name</label>
+            <button aria-controls="menu" aria-labelledby="email">
+            <img usemap="#nav"><img usemap="#map">
+            <map name="nav"></map>
+            <table><tr><th id=h1><td headers="h1 h2"></table>
+            "##
+            .as_bytes(),
+            &ServerConfig::default(),
+            &CheckOptions {
+                check_idrefs: true,
+                ..Default::default()
+            },
+            false,
+        )
+        .unwrap()
+        .filter_map(|link| match link {
+            Link::Reports(diagnostic) => {
+                assert_eq!(diagnostic.kind, DiagnosticKind::BadIdref);
+                Some(diagnostic.message)
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        links,
+        &[
+            "<input aria-describedby=\"email-hint email-error\"> refers to missing id \"email-error\"",
+            "<label for=\"name\"> refers to missing id \"name\"",
+            "<button aria-controls=\"menu\"> refers to missing id \"menu\"",
+            "<img usemap=\"#map\"> refers to missing map \"map\"",
+            "<td headers=\"h1 h2\"> refers to missing id \"h2\"",
+        ]
+    );
+}
+
+#[test]
+fn test_document_links_duplicate_anchors() {
+    use crate::paragraph::ParagraphHasher;
+
+    let doc = Document::new(Path::new("public/"), Path::new("public/page.html")).unwrap();
+
+    let mut doc_buf = DocumentBuffers::default();
+
+    let links = doc
+        .links_from_read::<_, ParagraphHasher>(
+            &mut doc_buf,
+            r##"
+            <h2 id="intro">Intro</h2>
+            <a id="usage" name="usage"></a>
+            <a name="intro"></a>
+            <p id=" intro ">
+            <div id="unique"></div>
+            "##
+            .as_bytes(),
+            &ServerConfig::default(),
+            &CheckOptions {
+                check_duplicate_anchors: true,
+                ..Default::default()
+            },
+            false,
+        )
+        .unwrap()
+        .filter_map(|link| match link {
+            Link::Reports(diagnostic) => {
+                assert_eq!(diagnostic.kind, DiagnosticKind::DuplicateAnchor);
+                Some(diagnostic.message)
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        links,
+        &[
+            "anchor #intro is defined by both <h2 id=\"intro\"> and <a name=\"intro\">",
+            "anchor #intro is defined by both <h2 id=\"intro\"> and <p id=\"intro\">",
+        ]
+    );
+}
+
+#[test]
+fn test_document_links_invalid_utf8() {
+    use crate::paragraph::ParagraphHasher;
+
+    let doc = Document::new(Path::new("public/"), Path::new("public/page.html")).unwrap();
+
+    let mut doc_buf = DocumentBuffers::default();
+
+    let links = doc
+        .links_from_read::<_, ParagraphHasher>(
+            &mut doc_buf,
+            &b"<a href=\"caf\xe9.html\"></a><img srcset=\"a.png 1x, \xff.png 2x\">\
+               <input type=image src=\"\xfe\"><p id=\"\xe9\"><a href=\"ok.html\"></a>\
+               <meta content=\"0; url=\xe0.html\" http-equiv=refresh>"[..],
+            &ServerConfig::default(),
+            &CheckOptions {
+                check_anchors: true,
+                check_duplicate_anchors: true,
+                ..Default::default()
+            },
+            false,
+        )
+        .unwrap()
+        .filter_map(|link| match link {
+            Link::Uses(used_link) => Some(used_link.href.0.to_owned()),
+            Link::Reports(diagnostic) => {
+                assert_eq!(diagnostic.kind, DiagnosticKind::InvalidUtf8);
+                Some(diagnostic.message)
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        links,
+        &[
+            "invalid UTF-8 at byte 12, in an attribute of <a>",
+            "invalid UTF-8 at byte 47, in an attribute of <img>",
+            "invalid UTF-8 at byte 80, in an attribute of <input>",
+            "invalid UTF-8 at byte 90, in an attribute of <p>",
+            "ok.html",
+            "invalid UTF-8 at byte 137, in an attribute of <meta>",
+        ]
+    );
+}
+
+#[test]
+fn test_document_links_inline_svg() {
+    use crate::paragraph::ParagraphHasher;
+
+    let doc = Document::new(Path::new("public/"), Path::new("public/index.html")).unwrap();
+
+    let mut doc_buf = DocumentBuffers::default();
+
+    let mut links = doc
+        .links_from_read::<_, ParagraphHasher>(
+            &mut doc_buf,
+            r##"
+            <svg viewBox="0 0 10 10">
+              <defs><linearGradient id="fade"></linearGradient></defs>
+              <use href="#fade"/>
+              <use xlink:href="icons.svg#search"/>
+              <image href="photo.png"/>
+              <a xlink:href="/about/"><text>About</text></a>
+            </svg>
+            "##
+            .as_bytes(),
+            &ServerConfig::default(),
+            &CheckOptions {
+                check_anchors: true,
+                ..Default::default()
+            },
+            false,
+        )
+        .unwrap();
+
+    let used_link = |x: &'static str| {
+        Link::Uses(UsedLink {
+            href: Href(x),
+            path: doc.path.clone(),
+            paragraph: None,
+        })
+    };
+
+    assert_eq!(
+        &links.next().unwrap(),
+        &Link::Defines(DefinedLink {
+            href: Href("#fade")
+        })
+    );
+    assert_eq!(&links.next().unwrap(), &used_link("#fade"));
+    assert_eq!(&links.next().unwrap(), &used_link("icons.svg#search"));
+    assert_eq!(&links.next().unwrap(), &used_link("photo.png"));
+    assert_eq!(&links.next().unwrap(), &used_link("about"));
+    assert_eq!(&links.next(), &None);
+}
+
+#[test]
+fn test_document_pdf_links() {
+    use crate::paragraph::ParagraphHasher;
+
+    let doc = Document::new(Path::new("public/"), Path::new("public/docs/manual.pdf")).unwrap();
+
+    let mut doc_buf = DocumentBuffers::default();
+
+    let pdf = pdf::build_test_pdf(1, &[], &["section 3"]);
+    let links = doc
+        .pdf_links_from_read::<_, ParagraphHasher>(&mut doc_buf, pdf.as_slice())
+        .unwrap()
+        .collect::<Vec<_>>();
+
+    let defined_link = |x: &'static str| Link::Defines(DefinedLink { href: Href(x) });
+
+    assert_eq!(
+        links,
+        &[
+            defined_link("docs/manual.pdf#page=1"),
+            defined_link("docs/manual.pdf#nameddest=section 3"),
+            defined_link("docs/manual.pdf#section 3"),
+        ]
+    );
+}
+
+#[test]
+fn test_document_join_text_fragment() {
+    let doc = Document::new(Path::new("public/"), Path::new("public/index.html")).unwrap();
+    let arena = bumpalo::Bump::new();
+
+    assert_eq!(
+        doc.join(&arena, true, "guide.html#:~:text=a%2Cb,c"),
+        Href("guide.html#:~:text=a%2Cb,c")
+    );
+    assert_eq!(
+        doc.join(&arena, true, "guide.html#s%C3%A9ance:~:text=a%20b"),
+        Href("guide.html#séance:~:text=a%20b")
+    );
+    assert_eq!(
+        doc.join(&arena, false, "guide.html#:~:text=a"),
+        Href("guide.html")
+    );
+}
+
+#[test]
+fn test_document_text() {
+    let doc = Document::new(Path::new("public/"), Path::new("public/index.html")).unwrap();
+
+    let mut doc_buf = DocumentBuffers::default();
+
+    let text = doc
+        .text_from_read(
+            &mut doc_buf,
+            r#"
+            <title>Title</title>
+            <style>p { color: red; }</style>
+            <h1>Hello &amp; <em>Welcome</em></h1>
+            <script>let x = "<p>";</script>
+            <template><p>Hidden <script></script></p></template>
+            <p>Visible
+            text</p>
+            "#
+            .as_bytes(),
+        )
+        .unwrap();
+
+    assert_eq!(text, "hello&welcomevisibletext");
+}
+
+#[test]
+fn test_document_links_attributes() {
+    use crate::paragraph::ParagraphHasher;
+
+    let cases: &[(&str, &[&str])] = &[
+        (r#"<a href="target">"#, &["target"]),
+        (r#"<area href="target">"#, &["target"]),
+        (r#"<link href="target">"#, &["target"]),
+        (
+            r#"<link imagesrcset="a.png 1x, b.png 2x">"#,
+            &["a.png", "b.png"],
+        ),
+        (r#"<img src="target">"#, &["target"]),
+        (
+            r#"<img srcset="a.png 300w, b.png 600w">"#,
+            &["a.png", "b.png"],
+        ),
+        (r#"<script src="target"></script>"#, &["target"]),
+        (r#"<iframe src="target"></iframe>"#, &["target"]),
+        (r#"<object data="target"></object>"#, &["target"]),
+        (r#"<video src="target"></video>"#, &["target"]),
+        (r#"<video poster="target"></video>"#, &["target"]),
+        (r#"<audio src="target"></audio>"#, &["target"]),
+        (r#"<source src="target">"#, &["target"]),
+        (
+            r#"<source srcset="a.png 1x, b.png 2x">"#,
+            &["a.png", "b.png"],
+        ),
+        (r#"<track src="target">"#, &["target"]),
+        (r#"<embed src="target">"#, &["target"]),
+        (r#"<input type="image" src="target">"#, &["target"]),
+        (r#"<input src="target" type="IMAGE">"#, &["target"]),
+        (r#"<input type="text" src="target">"#, &[]),
+        (r#"<form action="target"></form>"#, &["target"]),
+        (r#"<blockquote cite="target"></blockquote>"#, &["target"]),
+        (r#"<q cite="target"></q>"#, &["target"]),
+        (r#"<ins cite="target"></ins>"#, &["target"]),
+        (r#"<del cite="target"></del>"#, &["target"]),
+        (r#"<svg><use href="target"></use></svg>"#, &["target"]),
+        (r#"<svg><use xlink:href="target"></use></svg>"#, &["target"]),
+        (
+            r#"<div src="target" cite="target" poster="target"></div>"#,
+            &[],
+        ),
+    ];
+
+    let doc = Document::new(Path::new("public/"), Path::new("public/hello.html")).unwrap();
+    let mut doc_buf = DocumentBuffers::default();
+
+    for (html, expected) in cases {
+        let links = doc
+            .links_from_read::<_, ParagraphHasher>(
+                &mut doc_buf,
+                html.as_bytes(),
+                &ServerConfig::default(),
+                &CheckOptions::default(),
+                false,
+            )
+            .unwrap()
+            .filter_map(|link| match link {
+                Link::Uses(used_link) => Some(used_link.href.0.to_owned()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(&links, expected, "{}", html);
+        doc_buf.reset();
+    }
+}
+
+#[test]
+fn test_server_config_site_href() {
+    let config = ServerConfig {
+        site_urls: vec![
+            "https://docs.example.com".to_owned(),
+            "http://docs.example.com".to_owned(),
+        ],
+        ..Default::default()
+    };
+
+    let internal = |x: &'static str| SiteHref::Internal(Cow::Borrowed(x));
+
+    assert_eq!(
+        config.site_href("https://docs.example.com/guide/missing"),
+        internal("/guide/missing")
+    );
+    assert_eq!(
+        config.site_href("HTTP://Docs.Example.com/guide/"),
+        internal("/guide/")
+    );
+    assert_eq!(config.site_href("https://docs.example.com"), internal("/"));
+    assert_eq!(
+        config.site_href("https://docs.example.com#top"),
+        internal("/#top")
+    );
+    assert_eq!(
+        config.site_href("https://docs.example.com.evil.com/"),
+        SiteHref::External
+    );
+    assert_eq!(config.site_href("https://example.com/"), SiteHref::External);
+    assert_eq!(
+        config.site_href("mailto:foo@example.com"),
+        SiteHref::External
+    );
+    assert_eq!(config.site_href("guide/"), internal("guide/"));
+
+    assert_eq!(
+        ServerConfig::default().site_href("https://docs.example.com/guide/"),
+        SiteHref::External
+    );
+}
+
+#[test]
+fn test_server_config_path_prefix() {
+    let config = ServerConfig {
+        site_urls: vec!["https://example.com".to_owned()],
+        path_prefix: Some("docs".to_owned()),
+        ..Default::default()
+    };
+
+    let internal = |x: &'static str| SiteHref::Internal(Cow::Borrowed(x));
+
+    assert_eq!(config.site_href("/docs/guide/"), internal("/guide/"));
+    assert_eq!(config.site_href("/docs"), internal("/"));
+    assert_eq!(config.site_href("/docs?x=1"), internal("/?x=1"));
+    assert_eq!(
+        config.site_href("https://example.com/docs/guide/"),
+        internal("/guide/")
+    );
+    assert_eq!(config.site_href("guide/"), internal("guide/"));
+    assert_eq!(config.site_href("../guide/"), internal("../guide/"));
+    assert_eq!(config.site_href("#top"), internal("#top"));
+
+    assert_eq!(config.site_href("/guide/"), SiteHref::MissingPathPrefix);
+    assert_eq!(config.site_href("/docsearch/"), SiteHref::MissingPathPrefix);
+    assert_eq!(
+        config.site_href("https://example.com/blog/"),
+        SiteHref::External
+    );
+}
+
+#[test]
+fn test_document_links_site_url() {
+    use crate::paragraph::ParagraphHasher;
+
+    let doc = Document::new(Path::new("public/"), Path::new("public/guide/intro.html")).unwrap();
+
+    let mut doc_buf = DocumentBuffers::default();
+
+    let config = ServerConfig {
+        site_urls: vec!["https://docs.example.com".to_owned()],
+        ..Default::default()
+    };
+
+    let links = doc
+        .links_from_read::<_, ParagraphHasher>(
+            &mut doc_buf,
+            r#"
+            <base href="https://docs.example.com/guide/sub/">
+            <a href="https://docs.example.com/guide/missing" />
+            <a href="https://other.example.com/guide/missing" />
+            <a href="relative.html" />
+            <img srcset="https://docs.example.com/a.png 1x, https://cdn.example.com/b.png 2x">
+            "#
+            .as_bytes(),
+            &config,
+            &CheckOptions::default(),
+            false,
+        )
+        .unwrap();
+
+    let used_link = |x: &'static str| {
+        Link::Uses(UsedLink {
+            href: Href(x),
+            path: doc.path.clone(),
+            paragraph: None,
+        })
+    };
+
+    assert_eq!(
+        links.collect::<Vec<_>>(),
+        &[
+            used_link("guide/missing"),
+            used_link("guide/sub/relative.html"),
+            used_link("a.png"),
+        ]
     );
 }
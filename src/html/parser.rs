@@ -1,11 +1,31 @@
+use std::cell::Cell;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
+use std::mem;
+use std::rc::Rc;
+use std::str::Utf8Error;
+
 use bumpalo::collections::String as BumpString;
 use bumpalo::collections::Vec as BumpVec;
 use bumpalo::Bump;
-use html5gum::{Emitter, Error, State};
+use html5gum::{Emitter, Error, Reader, State};
 
-use crate::html::{DefinedLink, Document, Link, UsedLink};
+use crate::html::css::push_css_links;
+use crate::html::{
+    DefinedLink, Diagnostic, DiagnosticKind, Document, Href, Link, Redirect, Relation,
+    RelationKind, ServerConfig, SiteHref,
+};
 use crate::paragraph::ParagraphWalker;
 
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+/// Elements whose contents are not part of the visible text of a page.
+#[inline]
+fn is_hidden_text_tag(tag: &[u8]) -> bool {
+    matches!(tag, b"script" | b"style" | b"template" | b"title")
+}
+
 #[inline]
 fn is_paragraph_tag(tag: &[u8]) -> bool {
     tag == b"p" || tag == b"li" || tag == b"dt" || tag == b"dd"
@@ -17,7 +37,7 @@ fn try_normalize_href_value(input: &str) -> &str {
 }
 
 #[inline]
-fn is_bad_schema(url: &[u8]) -> bool {
+pub fn is_bad_schema(url: &[u8]) -> bool {
     // check if url is empty
     let first_char = match url.first() {
         Some(x) => x,
@@ -32,7 +52,7 @@ fn is_bad_schema(url: &[u8]) -> bool {
     // check if string before first : is a valid URL scheme
     // see RFC 2396, Appendix A for what constitutes a valid scheme
 
-    if !matches!(first_char, b'a'..=b'z' | b'A'..=b'Z') {
+    if !first_char.is_ascii_alphabetic() {
         return false;
     }
 
@@ -52,12 +72,116 @@ fn is_bad_schema(url: &[u8]) -> bool {
     false
 }
 
+/// Extract the URL from the `content` attribute of a `<meta http-equiv="refresh">`, such as
+/// `0; url=/new/`. Returns `None` if the page just reloads itself.
+///
+/// https://html.spec.whatwg.org/multipage/semantics.html#shared-declarative-refresh-steps
+fn parse_meta_refresh(content: &str) -> Option<&str> {
+    let rest = content
+        .trim_start()
+        .trim_start_matches(|c: char| c.is_ascii_digit() || c == '.')
+        .trim_start();
+    let mut rest = rest
+        .strip_prefix(&[';', ','][..])
+        .unwrap_or(rest)
+        .trim_start();
+
+    if let Some(prefix) = rest.get(..3) {
+        if prefix.eq_ignore_ascii_case("url") {
+            if let Some(url) = rest[3..].trim_start().strip_prefix('=') {
+                rest = url.trim_start();
+            }
+        }
+    }
+
+    let url = match rest.chars().next() {
+        Some(quote @ ('"' | '\'')) => {
+            let rest = &rest[1..];
+            &rest[..rest.find(quote).unwrap_or(rest.len())]
+        }
+        _ => rest.trim_end(),
+    };
+
+    if url.is_empty() {
+        None
+    } else {
+        Some(url)
+    }
+}
+
+/// Wraps a reader to keep track of how many bytes the tokenizer has consumed, so that problems
+/// can be reported with a byte offset.
+pub struct CountingReader<R> {
+    pub reader: R,
+    pub position: Rc<Cell<usize>>,
+}
+
+impl<R: Reader> Reader for CountingReader<R> {
+    type Error = R::Error;
+
+    fn read_byte(&mut self) -> Result<Option<u8>, Self::Error> {
+        let byte = self.reader.read_byte()?;
+        if byte.is_some() {
+            self.position.set(self.position.get() + 1);
+        }
+        Ok(byte)
+    }
+
+    fn try_read_string(&mut self, s: &[u8], case_sensitive: bool) -> Result<bool, Self::Error> {
+        let matched = self.reader.try_read_string(s, case_sensitive)?;
+        if matched {
+            self.position.set(self.position.get() + s.len());
+        }
+        Ok(matched)
+    }
+
+    fn read_until<'b>(
+        &'b mut self,
+        needle: &[u8],
+        char_buf: &'b mut [u8; 4],
+    ) -> Result<Option<&'b [u8]>, Self::Error> {
+        let chunk = self.reader.read_until(needle, char_buf)?;
+        if let Some(chunk) = chunk {
+            self.position.set(self.position.get() + chunk.len());
+        }
+        Ok(chunk)
+    }
+}
+
+/// An attribute that refers to ids of the same document, see `--check-idrefs`.
+struct IdRef {
+    tag: Vec<u8>,
+    attribute: Vec<u8>,
+    value: Vec<u8>,
+}
+
 #[derive(Default)]
 pub struct ParserBuffers {
     current_tag_name: Vec<u8>,
     current_attribute_name: Vec<u8>,
     current_attribute_value: Vec<u8>,
+    /// Byte offset of `current_attribute_value` in the document.
+    current_attribute_offset: usize,
     last_start_tag: Vec<u8>,
+    style_contents: Vec<u8>,
+    input_src: Vec<u8>,
+    input_src_offset: usize,
+    meta_content: Vec<u8>,
+    meta_content_offset: usize,
+    rel: Vec<u8>,
+    hreflang: Vec<u8>,
+    ids: BTreeSet<Vec<u8>>,
+    map_names: BTreeSet<Vec<u8>>,
+    idrefs: Vec<IdRef>,
+    /// Index of the current element in the document, so that `<a id=x name=x>` is not reported
+    /// as duplicate anchor.
+    current_element: usize,
+    /// For every anchor, the element that defines it first, see `--check-duplicate-anchors`.
+    anchors: BTreeMap<String, (usize, String)>,
+    /// The visible text of the document, see `HyperlinkEmitter::get_text`.
+    text: Vec<u8>,
+    /// How many elements containing hidden text are currently open.
+    hidden_text_depth: usize,
 }
 
 impl ParserBuffers {
@@ -65,7 +189,26 @@ impl ParserBuffers {
         self.current_tag_name.clear();
         self.current_attribute_name.clear();
         self.current_attribute_value.clear();
+        self.current_attribute_offset = 0;
         self.last_start_tag.clear();
+        self.style_contents.clear();
+        self.input_src.clear();
+        self.input_src_offset = 0;
+        self.meta_content.clear();
+        self.meta_content_offset = 0;
+        self.rel.clear();
+        self.hreflang.clear();
+        self.ids.clear();
+        self.map_names.clear();
+        self.idrefs.clear();
+        self.current_element = 0;
+        self.anchors.clear();
+        self.text.clear();
+        self.hidden_text_depth = 0;
+    }
+
+    pub fn text(&self) -> &[u8] {
+        &self.text
     }
 }
 
@@ -73,13 +216,28 @@ pub struct HyperlinkEmitter<'a, 'l, 'd, P: ParagraphWalker> {
     pub paragraph_walker: P,
     pub arena: &'a Bump,
     pub document: &'d Document,
+    pub server_config: &'d ServerConfig,
     pub link_buf: &'d mut BumpVec<'a, Link<'l, P::Paragraph>>,
     pub in_paragraph: bool,
     pub last_paragraph_i: usize,
     pub get_paragraphs: bool,
+    /// Collect the visible text of the document, for checking text fragments.
+    pub get_text: bool,
     pub buffers: &'d mut ParserBuffers,
     pub current_tag_is_closing: bool,
     pub check_anchors: bool,
+    pub check_idrefs: bool,
+    pub check_duplicate_anchors: bool,
+    /// The resolved `<base href>`, if the document has one.
+    pub base: Option<&'a str>,
+    pub seen_base: bool,
+    pub in_style: bool,
+    pub current_input_is_image: bool,
+    pub current_meta_is_refresh: bool,
+    /// The resolved href of the current tag, if any.
+    pub current_href: Option<Href<'a>>,
+    /// How many bytes of the document the tokenizer has read, see `CountingReader`.
+    pub position: Rc<Cell<usize>>,
 }
 
 impl<'a, 'l, 'd, P> HyperlinkEmitter<'a, 'l, 'd, P>
@@ -88,25 +246,26 @@ where
     P: ParagraphWalker,
 {
     fn extract_used_link(&mut self) {
-        let value = try_normalize_href_value(
-            std::str::from_utf8(&self.buffers.current_attribute_value).unwrap(),
+        let value = match std::str::from_utf8(&self.buffers.current_attribute_value) {
+            Ok(value) => try_normalize_href_value(value),
+            Err(error) => return self.report_invalid_utf8(error),
+        };
+
+        self.current_href = self.document.push_used_link(
+            self.arena,
+            self.server_config,
+            self.base,
+            self.check_anchors,
+            self.link_buf,
+            value,
         );
-
-        if is_bad_schema(value.as_bytes()) {
-            return;
-        }
-
-        self.link_buf.push(Link::Uses(UsedLink {
-            href: self.document.join(self.arena, self.check_anchors, value),
-            path: self.document.path.clone(),
-            paragraph: None,
-        }));
     }
 
     fn extract_used_link_srcset(&mut self) {
-        let value = try_normalize_href_value(
-            std::str::from_utf8(&self.buffers.current_attribute_value).unwrap(),
-        );
+        let value = match std::str::from_utf8(&self.buffers.current_attribute_value) {
+            Ok(value) => try_normalize_href_value(value),
+            Err(error) => return self.report_invalid_utf8(error),
+        };
 
         // https://html.spec.whatwg.org/multipage/images.html#srcset-attribute
         for value in value
@@ -114,23 +273,99 @@ where
             .filter_map(|candidate: &str| candidate.split_whitespace().next())
             .filter(|value| !value.is_empty())
         {
-            if is_bad_schema(value.as_bytes()) {
-                continue;
-            }
+            self.document.push_used_link(
+                self.arena,
+                self.server_config,
+                self.base,
+                self.check_anchors,
+                self.link_buf,
+                value,
+            );
+        }
+    }
 
-            self.link_buf.push(Link::Uses(UsedLink {
-                href: self.document.join(self.arena, self.check_anchors, value),
-                path: self.document.path.clone(),
-                paragraph: None,
-            }));
+    fn extract_base(&mut self) {
+        // Like browsers, only honor the first <base href>
+        if self.seen_base {
+            return;
+        }
+        self.seen_base = true;
+
+        let value = match std::str::from_utf8(&self.buffers.current_attribute_value) {
+            Ok(value) => try_normalize_href_value(value),
+            Err(error) => return self.report_invalid_utf8(error),
+        };
+        let value = BumpString::from_str_in(value, self.arena).into_bump_str();
+
+        if self
+            .link_buf
+            .iter()
+            .any(|link| matches!(link, Link::Uses(_)))
+        {
+            self.report(
+                DiagnosticKind::BadBase,
+                format!(
+                    "<base href=\"{}\"> appears after links, those were resolved without it",
+                    value
+                ),
+            );
+        }
+
+        self.base = match self.server_config.site_href(value) {
+            SiteHref::Internal(site_href) => self.document.resolve_base(self.arena, &site_href),
+            SiteHref::External | SiteHref::MissingPathPrefix => None,
+        };
+
+        if self.base.is_none() {
+            self.report(
+                DiagnosticKind::BadBase,
+                format!(
+                    "<base href=\"{}\"> points outside of the site, ignoring it",
+                    value
+                ),
+            );
         }
     }
 
+    fn report(&mut self, kind: DiagnosticKind, message: String) {
+        self.link_buf.push(Link::Reports(Diagnostic {
+            kind,
+            path: self.document.path.clone(),
+            message,
+        }));
+    }
+
+    /// Report an attribute value that is not valid UTF-8, instead of checking it.
+    fn report_invalid_utf8(&mut self, error: Utf8Error) {
+        let offset = self.buffers.current_attribute_offset + error.valid_up_to();
+        self.report(
+            DiagnosticKind::InvalidUtf8,
+            format!(
+                "invalid UTF-8 at byte {}, in an attribute of <{}>",
+                offset,
+                String::from_utf8_lossy(&self.buffers.current_tag_name)
+            ),
+        );
+    }
+
     fn extract_anchor_def(&mut self) {
+        if !self.check_anchors && !self.check_duplicate_anchors {
+            return;
+        }
+
+        if let Err(error) = std::str::from_utf8(&self.buffers.current_attribute_value) {
+            return self.report_invalid_utf8(error);
+        }
+
+        if self.check_duplicate_anchors {
+            self.extract_duplicate_anchor();
+        }
+
         if self.check_anchors {
             let mut href = BumpString::new_in(self.arena);
             let value = try_normalize_href_value(
-                std::str::from_utf8(&self.buffers.current_attribute_value).unwrap(),
+                // Checked above.
+                std::str::from_utf8(&self.buffers.current_attribute_value).unwrap_or_default(),
             );
             href.push('#');
             href.push_str(value);
@@ -141,17 +376,325 @@ where
         }
     }
 
+    fn extract_duplicate_anchor(&mut self) {
+        let value = try_normalize_href_value(
+            std::str::from_utf8(&self.buffers.current_attribute_value).unwrap_or_default(),
+        );
+
+        if value.is_empty() {
+            return;
+        }
+
+        let element = format!(
+            "<{} {}=\"{}\">",
+            String::from_utf8_lossy(&self.buffers.current_tag_name),
+            String::from_utf8_lossy(&self.buffers.current_attribute_name),
+            value
+        );
+
+        let message = match self.buffers.anchors.entry(value.to_owned()) {
+            Entry::Vacant(entry) => {
+                entry.insert((self.buffers.current_element, element));
+                return;
+            }
+            Entry::Occupied(entry) => {
+                let (first_element, first) = entry.get();
+                if *first_element == self.buffers.current_element {
+                    return;
+                }
+
+                format!(
+                    "anchor #{} is defined by both {} and {}",
+                    value, first, element
+                )
+            }
+        };
+
+        self.report(DiagnosticKind::DuplicateAnchor, message);
+    }
+
+    fn extract_id(&mut self) {
+        self.extract_anchor_def();
+
+        if self.check_idrefs {
+            let value = self.buffers.current_attribute_value.trim_ascii();
+            self.buffers.ids.insert(value.to_owned());
+        }
+    }
+
+    fn extract_map_name(&mut self) {
+        if self.check_idrefs {
+            let value = self.buffers.current_attribute_value.trim_ascii();
+            self.buffers.map_names.insert(value.to_owned());
+        }
+    }
+
+    fn extract_idref(&mut self) {
+        if self.check_idrefs {
+            self.buffers.idrefs.push(IdRef {
+                tag: self.buffers.current_tag_name.clone(),
+                attribute: self.buffers.current_attribute_name.clone(),
+                value: self.buffers.current_attribute_value.clone(),
+            });
+        }
+    }
+
+    /// Report all IDREF attributes that don't match an id. Can only be done once the entire
+    /// document has been read, as ids may be defined after they are referenced.
+    fn flush_idrefs(&mut self) {
+        for idref in mem::take(&mut self.buffers.idrefs) {
+            let is_usemap = idref.attribute == b"usemap";
+
+            for id in idref
+                .value
+                .split(|c| c.is_ascii_whitespace())
+                .filter(|id| !id.is_empty())
+            {
+                let is_defined = if is_usemap {
+                    // usemap is a hash-name reference to either the name or the id of a <map>.
+                    let name = id.strip_prefix(b"#").unwrap_or(id);
+                    self.buffers.map_names.contains(name) || self.buffers.ids.contains(name)
+                } else {
+                    self.buffers.ids.contains(id)
+                };
+
+                if !is_defined {
+                    let id = String::from_utf8_lossy(id);
+                    self.report(
+                        DiagnosticKind::BadIdref,
+                        format!(
+                            "<{} {}=\"{}\"> refers to missing {} \"{}\"",
+                            String::from_utf8_lossy(&idref.tag),
+                            String::from_utf8_lossy(&idref.attribute),
+                            String::from_utf8_lossy(&idref.value),
+                            if is_usemap { "map" } else { "id" },
+                            if is_usemap {
+                                id.trim_start_matches('#')
+                            } else {
+                                &id
+                            },
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    fn extract_style_attribute(&mut self) {
+        push_css_links(
+            self.document,
+            self.arena,
+            self.server_config,
+            self.base,
+            self.check_anchors,
+            self.link_buf,
+            &self.buffers.current_attribute_value,
+        );
+    }
+
+    fn flush_style_element(&mut self) {
+        push_css_links(
+            self.document,
+            self.arena,
+            self.server_config,
+            self.base,
+            self.check_anchors,
+            self.link_buf,
+            &self.buffers.style_contents,
+        );
+        self.buffers.style_contents.clear();
+        self.in_style = false;
+    }
+
+    fn flush_input_src(&mut self) {
+        if self.current_input_is_image && !self.buffers.input_src.is_empty() {
+            mem::swap(
+                &mut self.buffers.current_attribute_value,
+                &mut self.buffers.input_src,
+            );
+            mem::swap(
+                &mut self.buffers.current_attribute_offset,
+                &mut self.buffers.input_src_offset,
+            );
+            self.extract_used_link();
+            mem::swap(
+                &mut self.buffers.current_attribute_value,
+                &mut self.buffers.input_src,
+            );
+            mem::swap(
+                &mut self.buffers.current_attribute_offset,
+                &mut self.buffers.input_src_offset,
+            );
+        }
+
+        self.buffers.input_src.clear();
+        self.current_input_is_image = false;
+    }
+
+    fn flush_meta_refresh(&mut self) {
+        if self.current_meta_is_refresh {
+            let url = match std::str::from_utf8(&self.buffers.meta_content) {
+                Ok(content) => parse_meta_refresh(content),
+                Err(error) => {
+                    mem::swap(
+                        &mut self.buffers.current_attribute_offset,
+                        &mut self.buffers.meta_content_offset,
+                    );
+                    self.report_invalid_utf8(error);
+                    mem::swap(
+                        &mut self.buffers.current_attribute_offset,
+                        &mut self.buffers.meta_content_offset,
+                    );
+                    None
+                }
+            };
+
+            if let Some(url) = url {
+                let destination = self.document.push_used_link(
+                    self.arena,
+                    self.server_config,
+                    self.base,
+                    self.check_anchors,
+                    self.link_buf,
+                    url,
+                );
+
+                if let Some(destination) = destination {
+                    self.link_buf.push(Link::Redirects(Redirect {
+                        source: Href(self.arena.alloc_str(&self.document.href)),
+                        destination,
+                        path: self.document.path.clone(),
+                    }));
+                }
+            }
+        }
+
+        self.buffers.meta_content.clear();
+        self.current_meta_is_refresh = false;
+    }
+
+    /// Record `rel` values of the current `<link>` or `<a>` that are checked against other
+    /// documents, see `RelationKind`.
+    fn flush_relations(&mut self) {
+        if let (b"link" | b"a", Some(target)) = (
+            self.buffers.current_tag_name.as_slice(),
+            self.current_href.take(),
+        ) {
+            for rel in self.buffers.rel.split(|c| c.is_ascii_whitespace()) {
+                let kind = if rel.eq_ignore_ascii_case(b"canonical") {
+                    RelationKind::Canonical
+                } else if rel.eq_ignore_ascii_case(b"alternate")
+                    && !self.buffers.hreflang.is_empty()
+                {
+                    RelationKind::Alternate
+                } else if rel.eq_ignore_ascii_case(b"prev") || rel.eq_ignore_ascii_case(b"previous")
+                {
+                    RelationKind::Prev
+                } else if rel.eq_ignore_ascii_case(b"next") {
+                    RelationKind::Next
+                } else {
+                    continue;
+                };
+
+                let hreflang = if kind == RelationKind::Alternate {
+                    Some(
+                        &*self
+                            .arena
+                            .alloc_str(&String::from_utf8_lossy(&self.buffers.hreflang)),
+                    )
+                } else {
+                    None
+                };
+
+                self.link_buf.push(Link::Relates(Relation {
+                    kind,
+                    source: Href(self.arena.alloc_str(&self.document.href)),
+                    target: target.without_anchor(),
+                    hreflang,
+                    path: self.document.path.clone(),
+                }));
+            }
+        }
+
+        self.current_href = None;
+        self.buffers.rel.clear();
+        self.buffers.hreflang.clear();
+    }
+
     fn flush_old_attribute(&mut self) {
         match (
             self.buffers.current_tag_name.as_slice(),
             self.buffers.current_attribute_name.as_slice(),
         ) {
             (b"link" | b"area" | b"a", b"href") => self.extract_used_link(),
+            (b"base", b"href") => self.extract_base(),
             (b"a", b"name") => self.extract_anchor_def(),
-            (b"img" | b"script" | b"iframe", b"src") => self.extract_used_link(),
-            (b"img", b"srcset") => self.extract_used_link_srcset(),
+            (
+                b"img" | b"script" | b"iframe" | b"video" | b"audio" | b"source" | b"track"
+                | b"embed",
+                b"src",
+            ) => self.extract_used_link(),
+            (b"img" | b"source", b"srcset") => self.extract_used_link_srcset(),
+            (b"link", b"imagesrcset") => self.extract_used_link_srcset(),
+            (b"video", b"poster") => self.extract_used_link(),
             (b"object", b"data") => self.extract_used_link(),
-            (_, b"id") => self.extract_anchor_def(),
+            (b"form", b"action") => self.extract_used_link(),
+            (b"blockquote" | b"q" | b"ins" | b"del", b"cite") => self.extract_used_link(),
+            // SVG, both inline and standalone.
+            (b"a", b"xlink:href") => self.extract_used_link(),
+            (b"use" | b"image" | b"feimage" | b"textpath" | b"mpath", b"href" | b"xlink:href") => {
+                self.extract_used_link()
+            }
+            // <input src> is only loaded for type=image, and the type may come after src.
+            (b"input", b"src") => {
+                self.buffers.input_src.clear();
+                self.buffers
+                    .input_src
+                    .extend(&self.buffers.current_attribute_value);
+                self.buffers.input_src_offset = self.buffers.current_attribute_offset;
+            }
+            (b"input", b"type") => {
+                self.current_input_is_image = self
+                    .buffers
+                    .current_attribute_value
+                    .eq_ignore_ascii_case(b"image");
+            }
+            (b"link" | b"a", b"rel") => {
+                self.buffers.rel.clear();
+                self.buffers
+                    .rel
+                    .extend(&self.buffers.current_attribute_value);
+            }
+            (b"link" | b"a", b"hreflang") => {
+                self.buffers.hreflang.clear();
+                self.buffers
+                    .hreflang
+                    .extend(&self.buffers.current_attribute_value);
+            }
+            // Like for <input src>, http-equiv may come after content.
+            (b"meta", b"http-equiv") => {
+                self.current_meta_is_refresh = self
+                    .buffers
+                    .current_attribute_value
+                    .eq_ignore_ascii_case(b"refresh");
+            }
+            (b"meta", b"content") => {
+                self.buffers.meta_content.clear();
+                self.buffers
+                    .meta_content
+                    .extend(&self.buffers.current_attribute_value);
+                self.buffers.meta_content_offset = self.buffers.current_attribute_offset;
+            }
+            (b"map", b"name") => self.extract_map_name(),
+            (b"label", b"for")
+            | (b"input", b"list")
+            | (b"td" | b"th", b"headers")
+            | (b"img" | b"object", b"usemap")
+            | (_, b"aria-labelledby" | b"aria-describedby" | b"aria-controls") => {
+                self.extract_idref()
+            }
+            (_, b"id") => self.extract_id(),
+            (_, b"style") => self.extract_style_attribute(),
             _ => (),
         }
 
@@ -179,13 +722,22 @@ where
     }
 
     fn emit_string(&mut self, c: &[u8]) {
+        if self.in_style {
+            self.buffers.style_contents.extend(c);
+        }
+
         if self.get_paragraphs && self.in_paragraph {
             self.paragraph_walker.update(c);
         }
+
+        if self.get_text && self.buffers.hidden_text_depth == 0 {
+            self.buffers.text.extend(c);
+        }
     }
 
     fn init_start_tag(&mut self) {
         self.buffers.current_tag_name.clear();
+        self.buffers.current_element += 1;
         self.current_tag_is_closing = false;
     }
 
@@ -196,6 +748,17 @@ where
 
     fn emit_current_tag(&mut self) -> Option<State> {
         self.flush_old_attribute();
+        self.flush_input_src();
+        self.flush_meta_refresh();
+        self.flush_relations();
+
+        if self.get_text && is_hidden_text_tag(&self.buffers.current_tag_name) {
+            if self.current_tag_is_closing {
+                self.buffers.hidden_text_depth = self.buffers.hidden_text_depth.saturating_sub(1);
+            } else {
+                self.buffers.hidden_text_depth += 1;
+            }
+        }
 
         self.buffers.last_start_tag.clear();
         if !self.current_tag_is_closing {
@@ -203,6 +766,10 @@ where
                 .last_start_tag
                 .extend(&self.buffers.current_tag_name);
 
+            if self.buffers.current_tag_name == b"style" {
+                self.in_style = true;
+            }
+
             if is_paragraph_tag(&self.buffers.current_tag_name) {
                 self.in_paragraph = true;
                 self.last_paragraph_i = self.link_buf.len();
@@ -212,16 +779,15 @@ where
             let paragraph = self.paragraph_walker.finish_paragraph();
             if self.in_paragraph {
                 for link in &mut self.link_buf[self.last_paragraph_i..] {
-                    match link {
-                        Link::Uses(ref mut x) => {
-                            x.paragraph = paragraph.clone();
-                        }
-                        Link::Defines(_) => (),
+                    if let Link::Uses(ref mut x) = link {
+                        x.paragraph = paragraph.clone();
                     }
                 }
                 self.in_paragraph = false;
             }
             self.last_paragraph_i = self.link_buf.len();
+        } else if self.in_style && self.buffers.current_tag_name == b"style" {
+            self.flush_style_element();
         }
 
         self.buffers.current_tag_name.clear();
@@ -247,6 +813,10 @@ where
     }
 
     fn push_attribute_value(&mut self, s: &[u8]) {
+        if self.buffers.current_attribute_value.is_empty() {
+            // The tokenizer has just read `s`, unless it is a character reference.
+            self.buffers.current_attribute_offset = self.position.get().saturating_sub(s.len());
+        }
         self.buffers.current_attribute_value.extend(s);
     }
 
@@ -258,7 +828,13 @@ where
 
     fn emit_current_comment(&mut self) {}
     fn emit_current_doctype(&mut self) {}
-    fn emit_eof(&mut self) {}
+    fn emit_eof(&mut self) {
+        if self.in_style {
+            self.flush_style_element();
+        }
+
+        self.flush_idrefs();
+    }
     fn emit_error(&mut self, _: Error) {}
     fn init_comment(&mut self) {}
     fn init_doctype(&mut self) {}
@@ -280,3 +856,23 @@ fn test_is_bad_schema() {
     assert!(is_bad_schema(b"http:/"));
     assert!(!is_bad_schema(b"http/"));
 }
+
+#[test]
+fn test_parse_meta_refresh() {
+    assert_eq!(parse_meta_refresh("0; url=/new/"), Some("/new/"));
+    assert_eq!(parse_meta_refresh("0;URL='/new/#top'"), Some("/new/#top"));
+    assert_eq!(
+        parse_meta_refresh(" 5 , url = \"new.html\" "),
+        Some("new.html")
+    );
+    assert_eq!(
+        parse_meta_refresh("0; https://example.com"),
+        Some("https://example.com")
+    );
+    assert_eq!(
+        parse_meta_refresh("0.5;url=urlaub.html"),
+        Some("urlaub.html")
+    );
+    assert_eq!(parse_meta_refresh("30"), None);
+    assert_eq!(parse_meta_refresh(""), None);
+}
@@ -0,0 +1,191 @@
+//! Fragment identifiers of PDF files, see `--check-pdf-anchors`: `#page=N`, `#nameddest=name`
+//! and bare `#name` for named destinations.
+
+use std::collections::BTreeSet;
+
+use anyhow::Error;
+use lopdf::{Document, Object};
+
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+/// How deep the `/Dests` name tree is followed, in case it contains cycles.
+const MAX_NAME_TREE_DEPTH: usize = 32;
+
+/// Whether a parameter of a PDF fragment identifier refers to something in the file, as opposed
+/// to e.g. `zoom=200` or `view=Fit`, which are valid for any PDF.
+pub fn is_checked_parameter(parameter: &str) -> bool {
+    match parameter.split_once('=') {
+        Some((key, _)) => matches!(key, "page" | "nameddest"),
+        None => true,
+    }
+}
+
+/// All anchors (without `#`) that are defined by a PDF: `page=N` for every page, and both
+/// `nameddest=name` and `name` for every named destination.
+pub fn pdf_anchors(pdf: &[u8]) -> Result<Vec<String>, Error> {
+    let document = Document::load_mem(pdf)?;
+
+    let mut anchors: Vec<String> = (1..=document.page_iter().count())
+        .map(|page| format!("page={}", page))
+        .collect();
+
+    let mut names = BTreeSet::new();
+    let catalog = document.catalog()?;
+
+    // PDF 1.1 stores named destinations as a dictionary in the catalog.
+    if let Some(dests) = get_dict(&document, catalog.get(b"Dests").ok()) {
+        for (name, _) in dests.iter() {
+            names.insert(decode_text(name));
+        }
+    }
+
+    // Later versions use a name tree in the names dictionary.
+    if let Some(names_dict) = get_dict(&document, catalog.get(b"Names").ok()) {
+        collect_name_tree(&document, names_dict.get(b"Dests").ok(), &mut names, 0);
+    }
+
+    for name in names {
+        anchors.push(format!("nameddest={}", name));
+        anchors.push(name);
+    }
+
+    Ok(anchors)
+}
+
+fn get_dict<'a>(
+    document: &'a Document,
+    object: Option<&'a Object>,
+) -> Option<&'a lopdf::Dictionary> {
+    let (_, object) = document.dereference(object?).ok()?;
+    object.as_dict().ok()
+}
+
+fn collect_name_tree(
+    document: &Document,
+    node: Option<&Object>,
+    names: &mut BTreeSet<String>,
+    depth: usize,
+) {
+    let node = match get_dict(document, node) {
+        Some(node) if depth < MAX_NAME_TREE_DEPTH => node,
+        _ => return,
+    };
+
+    // Leaf nodes have alternating keys and values.
+    if let Ok(Object::Array(entries)) = node.get(b"Names") {
+        for key in entries.iter().step_by(2) {
+            if let Ok(key) = key.as_str() {
+                names.insert(decode_text(key));
+            }
+        }
+    }
+
+    if let Ok(Object::Array(kids)) = node.get(b"Kids") {
+        for kid in kids {
+            collect_name_tree(document, Some(kid), names, depth + 1);
+        }
+    }
+}
+
+/// Decode a PDF string, which is either UTF-16BE with a byte order mark, or for our purposes
+/// close enough to ASCII.
+fn decode_text(bytes: &[u8]) -> String {
+    match bytes.strip_prefix(b"\xfe\xff") {
+        Some(utf16) => {
+            let units: Vec<u16> = utf16
+                .chunks_exact(2)
+                .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        None => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// Build a PDF with the given number of pages and named destinations, for tests.
+#[cfg(test)]
+pub fn build_test_pdf(pages: usize, old_dests: &[&str], name_tree_dests: &[&str]) -> Vec<u8> {
+    use lopdf::dictionary;
+
+    let mut document = Document::with_version("1.5");
+    let pages_id = document.new_object_id();
+
+    let page_ids: Vec<Object> = (0..pages)
+        .map(|_| {
+            document
+                .add_object(dictionary! { "Type" => "Page", "Parent" => pages_id })
+                .into()
+        })
+        .collect();
+
+    let destination = || -> Object { vec![page_ids[0].clone(), "Fit".into()].into() };
+
+    let mut dests = lopdf::Dictionary::new();
+    for name in old_dests {
+        dests.set(*name, destination());
+    }
+
+    let mut leaf = Vec::new();
+    for name in name_tree_dests {
+        leaf.push(Object::string_literal(*name));
+        leaf.push(destination());
+    }
+    let leaf_id = document.add_object(dictionary! { "Names" => leaf });
+
+    document.objects.insert(
+        pages_id,
+        dictionary! {
+            "Type" => "Pages",
+            "Kids" => page_ids.clone(),
+            "Count" => pages as i64,
+        }
+        .into(),
+    );
+
+    let catalog_id = document.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+        "Dests" => dests,
+        "Names" => dictionary! { "Dests" => dictionary! { "Kids" => vec![leaf_id.into()] } },
+    });
+    document.trailer.set("Root", catalog_id);
+
+    let mut pdf = Vec::new();
+    document.save_to(&mut pdf).unwrap();
+    pdf
+}
+
+#[test]
+fn test_pdf_anchors() {
+    let pdf = build_test_pdf(2, &["intro"], &["section-3"]);
+
+    assert_eq!(
+        pdf_anchors(&pdf).unwrap(),
+        &[
+            "page=1",
+            "page=2",
+            "nameddest=intro",
+            "intro",
+            "nameddest=section-3",
+            "section-3",
+        ]
+    );
+
+    assert!(pdf_anchors(b"%PDF-1.4 garbage").is_err());
+}
+
+#[test]
+fn test_decode_text() {
+    assert_eq!(decode_text(b"intro"), "intro");
+    assert_eq!(decode_text(b"\xfe\xff\x00i\x00n"), "in");
+}
+
+#[test]
+fn test_is_checked_parameter() {
+    assert!(is_checked_parameter("page=3"));
+    assert!(is_checked_parameter("nameddest=intro"));
+    assert!(is_checked_parameter("intro"));
+    assert!(!is_checked_parameter("zoom=200"));
+    assert!(!is_checked_parameter("view=FitH"));
+}
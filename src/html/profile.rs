@@ -0,0 +1,198 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+/// How a web server maps URL paths to files.
+///
+/// With the default profile, `foo`, `foo/` and `foo/index.html` are all considered the same link.
+/// All other profiles make documents define their exact file path, and used links are looked up
+/// the way the respective server would look them up, so that trailing slashes and index files
+/// matter.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum ServerProfile {
+    /// Lenient matching, this is what hyperlink has always done.
+    #[default]
+    Default,
+    /// `/foo` serves `foo.html`, `/foo/` serves `foo/index.html`, `/foo` redirects to `/foo/`.
+    GithubPages,
+    /// Like GitHub Pages, but `/foo/` also serves `foo.html`.
+    Netlify,
+    /// `index index.html index.htm`, no `.html` extension inference, `/foo` redirects to `/foo/`.
+    NginxDefault,
+    /// `default.htm` and friends as index files, `/foo` redirects to `/foo/`, case is ignored.
+    Iis,
+    /// Only exact file paths and `/foo/` for `foo/index.html`.
+    Strict,
+}
+
+static PROFILE_NAMES: &[(&str, ServerProfile)] = &[
+    ("default", ServerProfile::Default),
+    ("github-pages", ServerProfile::GithubPages),
+    ("netlify", ServerProfile::Netlify),
+    ("nginx-default", ServerProfile::NginxDefault),
+    ("iis", ServerProfile::Iis),
+    ("strict", ServerProfile::Strict),
+];
+
+impl FromStr for ServerProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PROFILE_NAMES
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, profile)| *profile)
+            .ok_or_else(|| {
+                let names: Vec<_> = PROFILE_NAMES.iter().map(|(name, _)| *name).collect();
+                format!("expected one of {}", names.join(", "))
+            })
+    }
+}
+
+impl fmt::Display for ServerProfile {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let (name, _) = PROFILE_NAMES
+            .iter()
+            .find(|(_, profile)| profile == self)
+            .unwrap();
+        name.fmt(fmt)
+    }
+}
+
+impl ServerProfile {
+    /// Whether `/Foo.html` serves `foo.html`.
+    pub fn is_case_insensitive(&self) -> bool {
+        matches!(self, ServerProfile::Iis)
+    }
+
+    /// Whether `foo/index.html` defines `foo`, and `foo/` is the same link as `foo`.
+    pub fn collapses_index(&self) -> bool {
+        *self == ServerProfile::Default
+    }
+
+    /// Filenames that are served for a URL path ending in a slash, in order of preference.
+    pub fn index_files(&self) -> &'static [&'static str] {
+        match self {
+            ServerProfile::Default => &["index.html", "index.htm"],
+            ServerProfile::GithubPages | ServerProfile::Netlify | ServerProfile::Strict => {
+                &["index.html"]
+            }
+            ServerProfile::NginxDefault => &["index.html", "index.htm"],
+            ServerProfile::Iis => &["default.htm", "default.html", "index.htm", "index.html"],
+        }
+    }
+
+    /// Whether `/foo` serves `foo.html`.
+    fn infers_html_extension(&self) -> bool {
+        matches!(self, ServerProfile::GithubPages | ServerProfile::Netlify)
+    }
+
+    /// Whether `/foo/` serves `foo.html`.
+    fn infers_html_extension_with_slash(&self) -> bool {
+        matches!(self, ServerProfile::Netlify)
+    }
+
+    /// Whether `/foo` redirects to `/foo/` if `foo` is a directory.
+    fn redirects_directories(&self) -> bool {
+        !matches!(self, ServerProfile::Default | ServerProfile::Strict)
+    }
+
+    /// Find the defined href that the server would serve for a used href (without anchor).
+    pub fn resolve<'h>(
+        &self,
+        href: &'h str,
+        is_defined: impl Fn(&str) -> bool,
+    ) -> Option<Cow<'h, str>> {
+        if is_defined(href) {
+            return Some(Cow::Borrowed(href));
+        }
+
+        if self.collapses_index() {
+            return None;
+        }
+
+        let mut candidates = Vec::new();
+
+        if href.is_empty() || href.ends_with('/') {
+            for index in self.index_files() {
+                candidates.push(format!("{}{}", href, index));
+            }
+
+            if self.infers_html_extension_with_slash() && !href.is_empty() {
+                candidates.push(format!("{}.html", href.trim_end_matches('/')));
+            }
+        } else {
+            if self.infers_html_extension() {
+                candidates.push(format!("{}.html", href));
+            }
+
+            if self.redirects_directories() {
+                for index in self.index_files() {
+                    candidates.push(format!("{}/{}", href, index));
+                }
+            }
+        }
+
+        candidates
+            .into_iter()
+            .find(|candidate| is_defined(candidate))
+            .map(Cow::Owned)
+    }
+}
+
+#[cfg(test)]
+fn resolve(profile: ServerProfile, href: &str) -> Option<String> {
+    let files = ["index.html", "about.html", "guide/index.html", "img/a.png"];
+    profile
+        .resolve(href, |candidate| files.contains(&candidate))
+        .map(Cow::into_owned)
+}
+
+#[test]
+fn test_resolve_github_pages() {
+    let p = ServerProfile::GithubPages;
+    assert_eq!(resolve(p, "").as_deref(), Some("index.html"));
+    assert_eq!(resolve(p, "about.html").as_deref(), Some("about.html"));
+    assert_eq!(resolve(p, "about").as_deref(), Some("about.html"));
+    assert_eq!(resolve(p, "about/"), None);
+    assert_eq!(resolve(p, "guide").as_deref(), Some("guide/index.html"));
+    assert_eq!(resolve(p, "guide/").as_deref(), Some("guide/index.html"));
+    assert_eq!(resolve(p, "img/a.png/"), None);
+}
+
+#[test]
+fn test_resolve_netlify() {
+    let p = ServerProfile::Netlify;
+    assert_eq!(resolve(p, "about").as_deref(), Some("about.html"));
+    assert_eq!(resolve(p, "about/").as_deref(), Some("about.html"));
+}
+
+#[test]
+fn test_resolve_nginx_default() {
+    let p = ServerProfile::NginxDefault;
+    assert_eq!(resolve(p, "about"), None);
+    assert_eq!(resolve(p, "guide").as_deref(), Some("guide/index.html"));
+    assert_eq!(resolve(p, "guide/").as_deref(), Some("guide/index.html"));
+}
+
+#[test]
+fn test_resolve_strict() {
+    let p = ServerProfile::Strict;
+    assert_eq!(resolve(p, "about"), None);
+    assert_eq!(resolve(p, "guide"), None);
+    assert_eq!(resolve(p, "guide/").as_deref(), Some("guide/index.html"));
+    assert_eq!(
+        resolve(p, "guide/index.html").as_deref(),
+        Some("guide/index.html")
+    );
+}
+
+#[test]
+fn test_profile_names() {
+    for (name, profile) in PROFILE_NAMES {
+        assert_eq!(name.parse::<ServerProfile>().unwrap(), *profile);
+        assert_eq!(profile.to_string(), *name);
+    }
+
+    assert!("apache".parse::<ServerProfile>().is_err());
+}
@@ -0,0 +1,300 @@
+//! Redirect rules files as understood by static hosting providers: Netlify's `_redirects` and
+//! `netlify.toml`, and Vercel's `vercel.json`.
+
+use std::convert::TryInto;
+
+use anyhow::{anyhow, Error};
+
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+/// A single redirect rule, with source and destination as written in the rules file.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Rule {
+    pub source: String,
+    pub destination: String,
+    pub status: Option<u16>,
+}
+
+impl Rule {
+    /// Whether requests to the source are answered with something other than an error page.
+    pub fn defines_source(&self) -> bool {
+        !matches!(self.status, Some(status) if status >= 400)
+    }
+}
+
+/// Parse the rules file with the given file name, if it is one.
+pub fn parse(file_name: &str, contents: &str) -> Result<Vec<Rule>, Error> {
+    match file_name {
+        "_redirects" => Ok(parse_netlify_redirects(contents)),
+        "netlify.toml" => parse_netlify_toml(contents),
+        "vercel.json" => parse_vercel_json(contents),
+        _ => Err(anyhow!("not a redirects file: {}", file_name)),
+    }
+}
+
+/// Parse Netlify's `_redirects` format: `from [query conditions] to [status[!]] [conditions]`.
+fn parse_netlify_redirects(contents: &str) -> Vec<Rule> {
+    let mut rules = Vec::new();
+
+    for line in contents.lines() {
+        let line = match line.find('#') {
+            Some(0) => continue,
+            // Comments need to be separated by whitespace, `#` is also used in destinations.
+            Some(i) if line[..i].ends_with(char::is_whitespace) => &line[..i],
+            _ => line,
+        };
+
+        let mut parts = line.split_whitespace();
+        let source = match parts.next() {
+            Some(source) => source,
+            None => continue,
+        };
+
+        // Query parameter conditions such as `id=:id` come between source and destination.
+        let destination = match parts
+            .find(|part| part.starts_with('/') || part.contains("://") || !part.contains('='))
+        {
+            Some(destination) => destination,
+            None => continue,
+        };
+
+        let status = parts
+            .next()
+            .and_then(|status| status.trim_end_matches('!').parse().ok());
+
+        rules.push(Rule {
+            source: source.to_owned(),
+            destination: destination.to_owned(),
+            status,
+        });
+    }
+
+    rules
+}
+
+fn parse_netlify_toml(contents: &str) -> Result<Vec<Rule>, Error> {
+    let config: toml::Value = contents.parse()?;
+    let redirects = match config.get("redirects").and_then(|x| x.as_array()) {
+        Some(redirects) => redirects,
+        None => return Ok(Vec::new()),
+    };
+
+    Ok(redirects
+        .iter()
+        .filter_map(|redirect| {
+            Some(Rule {
+                source: redirect.get("from")?.as_str()?.to_owned(),
+                destination: redirect.get("to")?.as_str()?.to_owned(),
+                status: redirect
+                    .get("status")
+                    .and_then(|status| status.as_integer())
+                    .and_then(|status| status.try_into().ok()),
+            })
+        })
+        .collect())
+}
+
+fn parse_vercel_json(contents: &str) -> Result<Vec<Rule>, Error> {
+    let config: serde_json::Value = serde_json::from_str(contents)?;
+    let redirects = match config.get("redirects").and_then(|x| x.as_array()) {
+        Some(redirects) => redirects,
+        None => return Ok(Vec::new()),
+    };
+
+    Ok(redirects
+        .iter()
+        .filter_map(|redirect| {
+            Some(Rule {
+                source: redirect.get("source")?.as_str()?.to_owned(),
+                destination: redirect.get("destination")?.as_str()?.to_owned(),
+                status: redirect
+                    .get("statusCode")
+                    .and_then(|status| status.as_u64())
+                    .and_then(|status| status.try_into().ok()),
+            })
+        })
+        .collect())
+}
+
+/// Whether a path segment matches any number of segments: Netlify's `*` splat, or Vercel's
+/// `:name*` and `(.*)`.
+fn is_splat(segment: &str) -> bool {
+    segment == "*" || segment == "(.*)" || (segment.starts_with(':') && segment.ends_with('*'))
+}
+
+/// Whether a path segment is a placeholder, such as Netlify's `:slug`.
+fn is_placeholder(segment: &str) -> bool {
+    segment.starts_with(':') || segment.starts_with('(')
+}
+
+/// Whether a redirect source or destination contains splats or placeholders.
+pub fn is_pattern(path: &str) -> bool {
+    path.split('/')
+        .any(|segment| is_splat(segment) || is_placeholder(segment))
+}
+
+fn segments_match(pattern: &[&str], href: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => href.is_empty(),
+        Some((segment, rest)) if is_splat(segment) => {
+            (0..=href.len()).any(|i| segments_match(rest, &href[i..]))
+        }
+        Some((segment, rest)) if is_placeholder(segment) && segment.ends_with('+') => {
+            (1..=href.len()).any(|i| segments_match(rest, &href[i..]))
+        }
+        Some((segment, rest)) => match href.split_first() {
+            Some((href_segment, href_rest)) => {
+                let matches = if is_placeholder(segment) {
+                    !href_segment.is_empty()
+                } else {
+                    segment == href_segment
+                };
+
+                matches && segments_match(rest, href_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+/// Whether `href` (without anchor) is matched by a redirect source pattern. Both are in the
+/// canonical form produced by `Document::join`.
+pub fn pattern_matches(pattern: &str, href: &str) -> bool {
+    segments_match(&split_segments(pattern), &split_segments(href))
+}
+
+fn split_segments(path: &str) -> Vec<&str> {
+    if path.is_empty() {
+        Vec::new()
+    } else {
+        path.trim_end_matches('/').split('/').collect()
+    }
+}
+
+#[test]
+fn test_parse_netlify_redirects() {
+    let rules = parse_netlify_redirects(
+        r#"
+# Moved pages
+/old-page      /new-page
+/blog/*        /news/:splat   301!
+/store id=:id  /products/:id  302
+/ext           https://example.com/#top
+/private/*     /404.html      404  Role=admin  # comment
+        "#,
+    );
+
+    let rule = |source: &str, destination: &str, status| Rule {
+        source: source.to_owned(),
+        destination: destination.to_owned(),
+        status,
+    };
+
+    assert_eq!(
+        rules,
+        &[
+            rule("/old-page", "/new-page", None),
+            rule("/blog/*", "/news/:splat", Some(301)),
+            rule("/store", "/products/:id", Some(302)),
+            rule("/ext", "https://example.com/#top", None),
+            rule("/private/*", "/404.html", Some(404)),
+        ]
+    );
+
+    assert!(!rules[4].defines_source());
+}
+
+#[test]
+fn test_parse_netlify_toml() {
+    let rules = parse_netlify_toml(
+        r#"
+[build]
+publish = "public"
+
+[[redirects]]
+from = "/old"
+to = "/new"
+status = 301
+force = true
+
+[[redirects]]
+from = "/api/*"
+to = "https://api.example.com/:splat"
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        rules,
+        &[
+            Rule {
+                source: "/old".to_owned(),
+                destination: "/new".to_owned(),
+                status: Some(301),
+            },
+            Rule {
+                source: "/api/*".to_owned(),
+                destination: "https://api.example.com/:splat".to_owned(),
+                status: None,
+            },
+        ]
+    );
+
+    assert!(parse_netlify_toml("[build").is_err());
+}
+
+#[test]
+fn test_parse_vercel_json() {
+    let rules = parse_vercel_json(
+        r#"{
+            "redirects": [
+                { "source": "/docs/:path*", "destination": "/guide/:path*", "permanent": true },
+                { "source": "/old", "destination": "/new", "statusCode": 302 }
+            ]
+        }"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        rules,
+        &[
+            Rule {
+                source: "/docs/:path*".to_owned(),
+                destination: "/guide/:path*".to_owned(),
+                status: None,
+            },
+            Rule {
+                source: "/old".to_owned(),
+                destination: "/new".to_owned(),
+                status: Some(302),
+            },
+        ]
+    );
+
+    assert_eq!(parse_vercel_json("{}").unwrap(), &[]);
+}
+
+#[test]
+fn test_pattern_matches() {
+    assert!(pattern_matches("blog/*", "blog"));
+    assert!(pattern_matches("blog/*", "blog/2021/hello"));
+    assert!(!pattern_matches("blog/*", "blogs/hello"));
+    assert!(pattern_matches("*", ""));
+    assert!(pattern_matches("*", "anything/at/all"));
+    assert!(pattern_matches("news/:year/:slug", "news/2021/hello"));
+    assert!(!pattern_matches("news/:year/:slug", "news/2021"));
+    assert!(!pattern_matches(
+        "news/:year/:slug",
+        "news/2021/hello/world"
+    ));
+    assert!(pattern_matches("docs/:path*", "docs"));
+    assert!(pattern_matches("docs/:path+/edit", "docs/a/b/edit"));
+    assert!(!pattern_matches("docs/:path+/edit", "docs/edit"));
+    assert!(pattern_matches("guide/", "guide/"));
+
+    assert!(is_pattern("/blog/*"));
+    assert!(is_pattern("/news/:splat"));
+    assert!(!is_pattern("/new-page"));
+    assert!(!is_pattern("https://example.com/"));
+}
@@ -0,0 +1,84 @@
+//! Telling HTML documents without a file extension apart from other files by their first bytes,
+//! see `--sniff-html`. This follows the patterns that browsers use for resources without a
+//! `Content-Type`.
+//!
+//! https://mimesniff.spec.whatwg.org/#identifying-a-resource-with-an-unknown-mime-type
+
+use std::io::{self, Read};
+
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+/// How many bytes at the start of a file are looked at.
+const SNIFF_LEN: u64 = 512;
+
+/// Tags that a file needs to start with, after whitespace, to be considered HTML. Matched
+/// case-insensitively and only if followed by a space or `>`.
+static HTML_PATTERNS: &[&[u8]] = &[
+    b"<!doctype html",
+    b"<html",
+    b"<head",
+    b"<script",
+    b"<iframe",
+    b"<h1",
+    b"<div",
+    b"<font",
+    b"<table",
+    b"<a",
+    b"<style",
+    b"<title",
+    b"<b",
+    b"<body",
+    b"<br",
+    b"<p",
+    b"<!--",
+];
+
+/// Whether the file read by `read` looks like HTML. Returns a reader that still yields the
+/// entire file.
+pub fn sniff_html<R: Read>(read: R) -> io::Result<(bool, impl Read)> {
+    let mut prefix = Vec::new();
+    let mut read = read;
+    (&mut read).take(SNIFF_LEN).read_to_end(&mut prefix)?;
+
+    Ok((is_html(&prefix), io::Cursor::new(prefix).chain(read)))
+}
+
+fn is_html(prefix: &[u8]) -> bool {
+    let prefix = prefix.strip_prefix(b"\xef\xbb\xbf").unwrap_or(prefix);
+    let start = prefix
+        .iter()
+        .position(|c| !matches!(c, b'\t' | b'\n' | b'\x0c' | b'\r' | b' '))
+        .unwrap_or(prefix.len());
+    let prefix = &prefix[start..];
+
+    HTML_PATTERNS.iter().any(|pattern| {
+        prefix.len() > pattern.len()
+            && prefix[..pattern.len()].eq_ignore_ascii_case(pattern)
+            && matches!(prefix[pattern.len()], b' ' | b'>')
+    })
+}
+
+#[test]
+fn test_is_html() {
+    assert!(is_html(b"<!DOCTYPE html>\n<title>Guide</title>"));
+    assert!(is_html(b"\xef\xbb\xbf\n  <HTML lang=en>"));
+    assert!(is_html(b"<p>Moved to <a href=/new>here</a>"));
+    assert!(is_html(b"<!-- generated -->"));
+
+    assert!(!is_html(b"<?xml version=\"1.0\"?><svg>"));
+    assert!(!is_html(b"<pre>"));
+    assert!(!is_html(b"<a"));
+    assert!(!is_html(b"example.com\n"));
+    assert!(!is_html(b""));
+}
+
+#[test]
+fn test_sniff_html() {
+    let (is_html, mut read) = sniff_html(&b"<html><a href=foo>"[..]).unwrap();
+    let mut contents = String::new();
+    read.read_to_string(&mut contents).unwrap();
+
+    assert!(is_html);
+    assert_eq!(contents, "<html><a href=foo>");
+}
@@ -0,0 +1,148 @@
+//! Text fragments such as `#:~:text=prefix-,start,end,-suffix`, which link to a passage of text
+//! rather than an element.
+//!
+//! Both the page text and the directives are compared without whitespace and case, like
+//! `ParagraphWalker::update` does for paragraphs. That way, line breaks and formatting of the
+//! page do not matter.
+
+use super::try_percent_decode;
+
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+/// Separates the fragment directives from a regular anchor, as in `#anchor:~:text=foo`.
+const DIRECTIVE_DELIMITER: &str = ":~:";
+
+/// Split an anchor (starting with `#`) into the regular anchor and the fragment directives, if
+/// any.
+pub fn split_directives(anchor: &str) -> (&str, Option<&str>) {
+    match anchor.find(DIRECTIVE_DELIMITER) {
+        Some(i) => (&anchor[..i], Some(&anchor[i + DIRECTIVE_DELIMITER.len()..])),
+        None => (anchor, None),
+    }
+}
+
+/// Remove whitespace and case from text, for comparing it with text directives.
+pub fn normalize_text(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[derive(Debug, Default, Eq, PartialEq)]
+struct TextDirective {
+    prefix: String,
+    start: String,
+    end: Option<String>,
+    suffix: String,
+}
+
+impl TextDirective {
+    /// Parse the value of a `text=` directive, with all parts normalized. Returns `None` for
+    /// invalid directives.
+    fn parse(value: &str) -> Option<TextDirective> {
+        let mut parts: Vec<&str> = value.split(',').collect();
+        let mut directive = TextDirective::default();
+
+        if let Some(prefix) = parts.first().and_then(|part| part.strip_suffix('-')) {
+            directive.prefix = normalize_text(&try_percent_decode(prefix));
+            parts.remove(0);
+        }
+
+        if let Some(suffix) = parts.last().and_then(|part| part.strip_prefix('-')) {
+            directive.suffix = normalize_text(&try_percent_decode(suffix));
+            parts.pop();
+        }
+
+        let (start, end) = match parts.as_slice() {
+            [start] => (start, None),
+            [start, end] => (start, Some(end)),
+            _ => return None,
+        };
+
+        directive.start = normalize_text(&try_percent_decode(start));
+        directive.end = end.map(|end| normalize_text(&try_percent_decode(end)));
+
+        if directive.start.is_empty() || directive.end.as_deref() == Some("") {
+            return None;
+        }
+
+        Some(directive)
+    }
+
+    /// Whether the directive matches somewhere in `text`, which is normalized.
+    fn matches(&self, text: &str) -> bool {
+        let needle = format!("{}{}", self.prefix, self.start);
+
+        text.match_indices(&needle).any(|(i, _)| {
+            let after_start = i + needle.len();
+
+            match self.end {
+                None => text[after_start..].starts_with(&self.suffix),
+                Some(ref end) => text[after_start..]
+                    .match_indices(end)
+                    .any(|(j, _)| text[after_start + j + end.len()..].starts_with(&self.suffix)),
+            }
+        })
+    }
+}
+
+/// Whether all `text=` directives in `directives` (the part after `:~:`) match the normalized
+/// text of a page. Other directives are ignored.
+pub fn directives_match(directives: &str, text: &str) -> bool {
+    directives
+        .split('&')
+        .filter_map(|directive| directive.strip_prefix("text="))
+        .all(|value| matches!(TextDirective::parse(value), Some(directive) if directive.matches(text)))
+}
+
+#[test]
+fn test_split_directives() {
+    assert_eq!(split_directives("#foo"), ("#foo", None));
+    assert_eq!(split_directives("#:~:text=foo"), ("#", Some("text=foo")));
+    assert_eq!(
+        split_directives("#intro:~:text=foo"),
+        ("#intro", Some("text=foo"))
+    );
+}
+
+#[test]
+fn test_parse_directive() {
+    assert_eq!(
+        TextDirective::parse("an%20example,-text"),
+        Some(TextDirective {
+            start: "anexample".to_owned(),
+            suffix: "text".to_owned(),
+            ..Default::default()
+        })
+    );
+    assert_eq!(
+        TextDirective::parse("This-,is,Text%2C%20really"),
+        Some(TextDirective {
+            prefix: "this".to_owned(),
+            start: "is".to_owned(),
+            end: Some("text,really".to_owned()),
+            ..Default::default()
+        })
+    );
+    assert_eq!(TextDirective::parse("a,b,c"), None);
+    assert_eq!(TextDirective::parse(""), None);
+    assert_eq!(TextDirective::parse("foo-,-bar"), None);
+}
+
+#[test]
+fn test_directives_match() {
+    let text = normalize_text("The quick brown fox\n  jumps over the LAZY dog.");
+
+    assert!(directives_match("text=quick%20brown", &text));
+    assert!(directives_match("text=lazy dog", &text));
+    assert!(directives_match("text=quick,dog", &text));
+    assert!(directives_match("text=the-,quick,-brown", &text));
+    assert!(directives_match("text=quick&text=fox", &text));
+    assert!(!directives_match("text=quick&text=cat", &text));
+    assert!(!directives_match("text=dog,quick", &text));
+    assert!(!directives_match("text=a-,quick", &text));
+    assert!(!directives_match("text=quick,-fox", &text));
+    assert!(!directives_match("text=", &text));
+}
@@ -0,0 +1,143 @@
+use std::fmt;
+use std::str::FromStr;
+
+use unicode_normalization::char::canonical_combining_class;
+use unicode_normalization::UnicodeNormalization;
+
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+/// A Unicode normalization form that hrefs are compared in, see `--unicode-normalization`.
+///
+/// File names created on macOS are often decomposed (NFD), while most editors write composed
+/// (NFC) text, so `café.html` may be spelled differently in a link and in the file system.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum UnicodeForm {
+    Nfc,
+    Nfd,
+    Nfkc,
+    Nfkd,
+}
+
+static FORM_NAMES: &[(&str, UnicodeForm)] = &[
+    ("nfc", UnicodeForm::Nfc),
+    ("nfd", UnicodeForm::Nfd),
+    ("nfkc", UnicodeForm::Nfkc),
+    ("nfkd", UnicodeForm::Nfkd),
+];
+
+impl FromStr for UnicodeForm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        FORM_NAMES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|(_, form)| *form)
+            .ok_or_else(|| {
+                let names: Vec<_> = FORM_NAMES.iter().map(|(name, _)| *name).collect();
+                format!("expected one of {}", names.join(", "))
+            })
+    }
+}
+
+impl fmt::Display for UnicodeForm {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let (name, _) = FORM_NAMES.iter().find(|(_, form)| form == self).unwrap();
+        name.to_uppercase().fmt(fmt)
+    }
+}
+
+impl UnicodeForm {
+    pub fn normalize(&self, s: &str) -> String {
+        match self {
+            UnicodeForm::Nfc => s.nfc().collect(),
+            UnicodeForm::Nfd => s.nfd().collect(),
+            UnicodeForm::Nfkc => s.nfkc().collect(),
+            UnicodeForm::Nfkd => s.nfkd().collect(),
+        }
+    }
+
+    /// Spell out the characters that this form would change, as hrefs that only differ in
+    /// normalization look the same. Everything else is left readable, such as the rest of an href
+    /// in a non-Latin script.
+    pub fn escape_unnormalized(&self, s: &str) -> String {
+        let mut escaped = String::new();
+        let mut cluster = String::new();
+
+        let mut flush = |cluster: &mut String| {
+            if self.normalize(cluster) == *cluster {
+                escaped.push_str(cluster);
+            } else {
+                for c in cluster.chars() {
+                    if c.is_ascii() {
+                        escaped.push(c);
+                    } else {
+                        escaped.extend(c.escape_unicode());
+                    }
+                }
+            }
+            cluster.clear();
+        };
+
+        for c in s.chars() {
+            // Combining marks and Hangul vowels and final consonants are normalized together
+            // with the character before them.
+            let continues_cluster =
+                canonical_combining_class(c) != 0 || ('\u{1160}'..='\u{11ff}').contains(&c);
+
+            if !continues_cluster {
+                flush(&mut cluster);
+            }
+            cluster.push(c);
+        }
+
+        flush(&mut cluster);
+        escaped
+    }
+}
+
+#[test]
+fn test_normalize() {
+    let composed = "caf\u{e9}.html";
+    let decomposed = "cafe\u{301}.html";
+
+    assert_eq!(UnicodeForm::Nfc.normalize(decomposed), composed);
+    assert_eq!(UnicodeForm::Nfd.normalize(composed), decomposed);
+    assert_eq!(UnicodeForm::Nfkc.normalize("\u{fb01}le.html"), "file.html");
+    assert_eq!(
+        UnicodeForm::Nfc.normalize("\u{fb01}le.html"),
+        "\u{fb01}le.html"
+    );
+}
+
+#[test]
+fn test_escape_unnormalized() {
+    assert_eq!(
+        UnicodeForm::Nfc.escape_unnormalized("cafe\u{301}/caf\u{e9}.html"),
+        "cafe\\u{301}/caf\u{e9}.html"
+    );
+    assert_eq!(
+        UnicodeForm::Nfd.escape_unnormalized("cafe\u{301}/caf\u{e9}.html"),
+        "cafe\u{301}/caf\\u{e9}.html"
+    );
+    assert_eq!(
+        UnicodeForm::Nfc.escape_unnormalized("\u{1112}\u{1161}\u{11ab}/\u{c5b8}\u{c5b4}"),
+        "\\u{1112}\\u{1161}\\u{11ab}/\u{c5b8}\u{c5b4}"
+    );
+    assert_eq!(
+        UnicodeForm::Nfc.escape_unnormalized("ドキュメント/入門.html"),
+        "ドキュメント/入門.html"
+    );
+}
+
+#[test]
+fn test_form_names() {
+    for (name, form) in FORM_NAMES {
+        assert_eq!(name.parse::<UnicodeForm>().unwrap(), *form);
+        assert_eq!(form.to_string(), name.to_uppercase());
+    }
+
+    assert_eq!("NFC".parse::<UnicodeForm>().unwrap(), UnicodeForm::Nfc);
+    assert!("nfx".parse::<UnicodeForm>().is_err());
+}
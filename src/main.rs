@@ -1,25 +1,45 @@
+mod archive;
 mod collector;
 mod html;
 mod markdown;
 mod paragraph;
+mod walk;
 
 use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, Read};
 use std::mem;
 use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::Arc;
 
 use anyhow::{anyhow, Context, Error};
+use archive::Archive;
 use clap::Parser;
-use jwalk::WalkDir;
 use markdown::DocumentSource;
 use rayon::prelude::*;
-
-use collector::{BrokenLinkCollector, LinkCollector, UsedLinkCollector};
-use html::{DefinedLink, Document, DocumentBuffers, Link};
+use regex::Regex;
+use walk::{walk_files, Symlinks};
+
+use collector::{AnchorChecks, BrokenLinkCollector, LinkCollector, UsedLinkCollector};
+use html::{
+    sniff_html, CheckOptions, DefinedLink, Diagnostic, DiagnosticKind, Document, DocumentBuffers,
+    Link, Redirect, ServerConfig, ServerProfile, UnicodeForm, EPUB_CONTAINER_HREF,
+};
 use paragraph::{DebugParagraphWalker, NoopParagraphWalker, ParagraphHasher, ParagraphWalker};
 
 static MARKDOWN_FILES: &[&str] = &["md", "mdx"];
 static HTML_FILES: &[&str] = &["htm", "html"];
+/// Read as HTML with `--epub`.
+static XHTML_FILES: &[&str] = &["xhtml"];
+/// Package files of EPUB publications besides `META-INF/container.xml`, read with `--epub`.
+static EPUB_FILES: &[&str] = &["opf", "ncx"];
+static CSS_FILES: &[&str] = &["css"];
+static PDF_FILES: &[&str] = &["pdf"];
+/// Parsed like HTML for ids and links, but defined like any other file rather than as a page.
+static SVG_FILES: &[&str] = &["svg"];
+static XML_FILES: &[&str] = &["xml", "rss", "atom"];
+static REDIRECT_FILES: &[&str] = &["_redirects", "netlify.toml", "vercel.json"];
 
 #[derive(Parser)]
 #[clap(about, version)]
@@ -28,6 +48,9 @@ struct Cli {
     ///
     /// This will be assumed to be the root path of your server as well, so
     /// href="/foo" will resolve to that folder's subfolder foo.
+    ///
+    /// Can also be a .tar, .tar.gz, .tgz or .zip archive, or a folder in one
+    /// such as site.zip!/public. Archives are read without extracting them.
     #[structopt(verbatim_doc_comment)]
     base_path: Option<PathBuf>,
 
@@ -39,10 +62,113 @@ struct Cli {
     #[clap(long = "check-anchors")]
     check_anchors: bool,
 
+    /// Check that attributes referring to ids of the same page, such as <label for>,
+    /// aria-labelledby or <img usemap>, point to existing ids.
+    #[clap(long = "check-idrefs")]
+    check_idrefs: bool,
+
+    /// Report anchors that are defined more than once in a page, either by two ids or by an id
+    /// and an <a name>. Links to such anchors land on an unpredictable element.
+    #[clap(long = "check-duplicate-anchors")]
+    check_duplicate_anchors: bool,
+
+    /// Read linked PDF files for their page count and named destinations, so that anchors such as
+    /// #page=42 and #nameddest=intro can be checked. Only has an effect with --check-anchors.
+    #[clap(long = "check-pdf-anchors")]
+    check_pdf_anchors: bool,
+
+    /// Check an EPUB publication: Read XHTML content documents as HTML, check that the package
+    /// document listed in META-INF/container.xml exists, that all its manifest items exist, and
+    /// that the spine and the NCX table of contents point to existing content documents. This is
+    /// the default if the checked path is an .epub file.
+    #[clap(long = "epub")]
+    epub: bool,
+
+    /// Also read files with this extension as HTML, such as xhtml or shtml. Can be passed multiple
+    /// times. Files ending in .html or .htm are always read as HTML.
+    #[clap(long = "html-extension", value_name = "EXTENSION")]
+    html_extensions: Vec<String>,
+
+    /// Read files without an extension as HTML if they start like an HTML document, such as with
+    /// <!DOCTYPE html> or <html>. Their links and anchors are then checked like those of any other
+    /// page, which helps with sites that serve clean URLs from files without an extension.
+    #[clap(long = "sniff-html")]
+    sniff_html: bool,
+
+    /// Follow symlinks to files and folders, and check them under the path of the symlink, like a
+    /// web server that follows symlinks would serve them. Symlinks to one of their own parent
+    /// folders are reported and not followed.
+    #[clap(long = "follow-symlinks")]
+    follow_symlinks: bool,
+
+    /// With --follow-symlinks, only define the hrefs of symlinked files whose target is read
+    /// already under another path, instead of reading it twice. Anchors are then checked on the
+    /// target, and the links in such files are only checked once.
+    #[clap(long = "alias-symlinks", requires = "follow-symlinks")]
+    alias_symlinks: bool,
+
+    /// A regular expression for anchors that are valid on every page, such as 'L\d+' for line
+    /// numbers or '/.*' for client-side routes. It has to match the entire anchor, a leading # is
+    /// optional. Can be passed multiple times. # and #top are always valid.
+    #[clap(long = "valid-anchor")]
+    valid_anchors: Vec<String>,
+
+    /// URL under which the site is deployed, such as https://example.com. Absolute links to this
+    /// URL are checked like internal links. Can be passed multiple times, e.g. for www and non-www
+    /// variants.
+    #[clap(long = "site-url")]
+    site_urls: Vec<String>,
+
+    /// How the web server that serves the site maps URLs to files. One of default, github-pages,
+    /// netlify, nginx-default, iis, strict. Every profile other than default distinguishes between
+    /// foo, foo/ and foo/index.html like the respective server does.
+    #[clap(long = "server-profile", default_value = "default")]
+    server_profile: ServerProfile,
+
+    /// URL path under which the site is deployed, such as /docs. Root-relative links need to
+    /// start with it, and it is stripped before resolving them against the static file path.
+    #[clap(long = "path-prefix")]
+    path_prefix: Option<String>,
+
+    /// Report redirects that take more than this many hops to reach their final destination, be
+    /// it through <meta http-equiv="refresh"> stub pages or redirect rules. Redirect loops are
+    /// always reported.
+    #[clap(long = "max-redirect-chain")]
+    max_redirect_chain: Option<usize>,
+
+    /// Check that link relations are consistent across pages: A page linked as canonical must not
+    /// declare another page as canonical, hreflang alternates must link back to each other, and
+    /// rel=prev/next links must form chains.
+    #[clap(long = "check-link-relations")]
+    check_link_relations: bool,
+
+    /// Report HTML pages that are not listed in the sitemap. Pages that only redirect elsewhere
+    /// are exempt.
+    #[clap(long = "check-sitemap")]
+    check_sitemap: bool,
+
+    /// Report links that only work because the server ignores case, such as /Guide.html for
+    /// guide.html. Requires --server-profile iis, as with every other profile such links are
+    /// broken links already.
+    #[clap(long = "check-case")]
+    check_case: bool,
+
+    /// Compare hrefs and file names after normalizing both to a Unicode normalization form (NFC,
+    /// NFD, NFKC or NFKD). Links that only match a file after normalization, such as a composed é
+    /// in the link and a decomposed é in a file name created on macOS, are reported as warnings
+    /// instead of broken links.
+    #[clap(long = "unicode-normalization", value_name = "FORM")]
+    unicode_normalization: Option<UnicodeForm>,
+
     /// Path to directory of markdown files to use for reporting errors.
     #[clap(long = "sources")]
     sources_path: Option<PathBuf>,
 
+    /// Also read files with this extension in --sources as Markdown. Can be passed multiple times.
+    /// Files ending in .md or .mdx are always read.
+    #[clap(long = "markdown-extension", value_name = "EXTENSION")]
+    markdown_extensions: Vec<String>,
+
     /// Enable specialized output for GitHub actions.
     #[clap(long = "github-actions")]
     github_actions: bool,
@@ -89,7 +215,25 @@ fn main() -> Result<(), Error> {
         base_path,
         threads,
         check_anchors,
+        check_idrefs,
+        check_duplicate_anchors,
+        check_pdf_anchors,
+        epub,
+        html_extensions,
+        sniff_html,
+        follow_symlinks,
+        alias_symlinks,
+        valid_anchors,
+        site_urls,
+        server_profile,
+        path_prefix,
+        max_redirect_chain,
+        check_link_relations,
+        check_sitemap,
+        check_case,
+        unicode_normalization,
         sources_path,
+        markdown_extensions,
         github_actions,
         subcommand,
     } = Cli::parse();
@@ -120,7 +264,7 @@ fn main() -> Result<(), Error> {
             // Invalid invocation. Ultra hack to show help if no arguments are provided. Structopt
             // does not seem to have a functional way to require either an argument or a
             // subcommand. required_if etc don't actually work.
-            let help_message = Cli::try_parse_from(&["hyperlink", "--help"])
+            let help_message = Cli::try_parse_from(["hyperlink", "--help"])
                 .map(|_| ())
                 .unwrap_err();
             help_message.print()?;
@@ -128,17 +272,123 @@ fn main() -> Result<(), Error> {
         }
     };
 
-    if sources_path.is_some() {
-        check_links::<ParagraphHasher>(base_path, check_anchors, sources_path, github_actions)
+    let server_config = ServerConfig {
+        site_urls: site_urls
+            .iter()
+            .map(|url| url.trim_end_matches('/').to_owned())
+            .collect(),
+        path_prefix: path_prefix
+            .as_deref()
+            .map(|prefix| prefix.trim_matches('/'))
+            .filter(|prefix| !prefix.is_empty())
+            .map(str::to_owned),
+        profile: server_profile,
+    };
+
+    let options = CheckOptions {
+        check_anchors,
+        check_idrefs,
+        check_duplicate_anchors,
+        check_pdf_anchors,
+        epub: epub
+            || matches!(Archive::from_base_path(&base_path), Some(archive) if archive.is_epub()),
+        html_extensions: trim_extensions(html_extensions),
+        sniff_html,
+        follow_symlinks,
+        alias_symlinks,
+    };
+
+    if check_case && !server_config.profile.is_case_insensitive() {
+        return Err(anyhow!(
+            "--check-case requires --server-profile iis, with other profiles links that differ in case are broken links"
+        ));
+    }
+
+    let valid_anchors = valid_anchors
+        .iter()
+        .map(|pattern| {
+            let pattern = pattern.strip_prefix('#').unwrap_or(pattern);
+            Regex::new(&format!("^(?:{})$", pattern))
+                .with_context(|| format!("Invalid --valid-anchor {}", pattern))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let site_checks = SiteChecks {
+        max_redirect_chain,
+        check_link_relations,
+        check_sitemap,
+        check_case,
+        unicode_form: unicode_normalization,
+    };
+
+    let sources = sources_path.map(|path| Sources {
+        path,
+        extensions: trim_extensions(markdown_extensions),
+    });
+
+    if sources.is_some() {
+        check_links::<ParagraphHasher>(
+            base_path,
+            &server_config,
+            &options,
+            valid_anchors,
+            &site_checks,
+            sources,
+            github_actions,
+        )
     } else {
-        check_links::<NoopParagraphWalker>(base_path, check_anchors, sources_path, github_actions)
+        check_links::<NoopParagraphWalker>(
+            base_path,
+            &server_config,
+            &options,
+            valid_anchors,
+            &site_checks,
+            sources,
+            github_actions,
+        )
     }
 }
 
+/// Accept extensions with a leading dot, such as `.shtml`.
+fn trim_extensions(extensions: Vec<String>) -> Vec<String> {
+    extensions
+        .into_iter()
+        .map(|extension| extension.trim_start_matches('.').to_owned())
+        .collect()
+}
+
+/// The Markdown files a site was generated from, see `--sources`.
+struct Sources {
+    path: PathBuf,
+    /// Extensions of Markdown files besides `MARKDOWN_FILES`.
+    extensions: Vec<String>,
+}
+
+/// Checks that need to look at the whole site, run after all files have been read.
+struct SiteChecks {
+    max_redirect_chain: Option<usize>,
+    check_link_relations: bool,
+    check_sitemap: bool,
+    check_case: bool,
+    unicode_form: Option<UnicodeForm>,
+}
+
+/// Everything that is reported for a single (HTML or Markdown) file.
+#[derive(Default)]
+struct FileReport {
+    bad_links: BTreeSet<(Option<usize>, String)>,
+    bad_anchors: BTreeSet<(Option<usize>, String)>,
+    errors: BTreeSet<(Option<usize>, String)>,
+    warnings: BTreeSet<(Option<usize>, String)>,
+}
+
 fn check_links<P: ParagraphWalker>(
     base_path: PathBuf,
-    check_anchors: bool,
-    sources_path: Option<PathBuf>,
+    server_config: &ServerConfig,
+    options: &CheckOptions,
+    valid_anchors: Vec<Regex>,
+    site_checks: &SiteChecks,
+    sources: Option<Sources>,
     github_actions: bool,
 ) -> Result<(), Error>
 where
@@ -148,8 +398,9 @@ where
 
     let html_result = extract_html_links::<BrokenLinkCollector<_>, P>(
         &base_path,
-        check_anchors,
-        sources_path.is_some(),
+        server_config,
+        options,
+        sources.is_some(),
     )?;
 
     let used_links_len = html_result.collector.used_links_count();
@@ -158,19 +409,39 @@ where
         used_links_len, html_result.file_count, html_result.documents_count,
     );
 
-    let mut bad_links_and_anchors = BTreeMap::new();
+    let mut bad_links_and_anchors: BTreeMap<_, FileReport> = BTreeMap::new();
     let mut bad_links_count = 0;
     let mut bad_anchors_count = 0;
 
+    let text_fragment_pages = html_result
+        .collector
+        .text_fragment_pages(server_config.profile);
+
+    let page_texts = if text_fragment_pages.is_empty() {
+        BTreeMap::new()
+    } else {
+        println!("Reading pages linked with text fragments");
+        extract_page_texts(&base_path, server_config, options, &text_fragment_pages)?
+    };
+
+    let anchor_checks = AnchorChecks {
+        page_texts,
+        valid_anchors,
+    };
+
     let mut broken_links = html_result
         .collector
-        .get_broken_links(check_anchors)
+        .get_broken_links(
+            server_config.profile,
+            &anchor_checks,
+            site_checks.unicode_form,
+        )
         .peekable();
 
     let paragraps_to_sourcefile = if broken_links.peek().is_some() {
-        if let Some(ref sources_path) = sources_path {
+        if let Some(ref sources) = sources {
             println!("Found some broken links, reading source files");
-            extract_markdown_paragraphs::<P>(sources_path)?
+            extract_markdown_paragraphs::<P>(sources)?
         } else {
             BTreeMap::new()
         }
@@ -181,6 +452,14 @@ where
     for broken_link in broken_links {
         let mut had_sources = false;
 
+        let href = match broken_link.case_mismatch {
+            Some(ref defined) => format!(
+                "{} (only differs in case from /{})",
+                broken_link.link.href, defined
+            ),
+            None => broken_link.link.href.clone(),
+        };
+
         if broken_link.hard_404 {
             bad_links_count += 1;
         } else {
@@ -193,56 +472,134 @@ where
                 had_sources = true;
 
                 for (source, lineno) in *document_sources {
-                    let (bad_links, bad_anchors) = bad_links_and_anchors
+                    let report = bad_links_and_anchors
                         .entry((!had_sources, source.path.clone()))
-                        .or_insert_with(|| (BTreeSet::new(), BTreeSet::new()));
+                        .or_insert_with(Default::default);
 
                     if broken_link.hard_404 {
-                        bad_links
+                        &mut report.bad_links
                     } else {
-                        bad_anchors
+                        &mut report.bad_anchors
                     }
-                    .insert((Some(*lineno), broken_link.link.href.clone()));
+                    .insert((Some(*lineno), href.clone()));
                 }
             }
         }
 
         if !had_sources {
-            let (bad_links, bad_anchors) = bad_links_and_anchors
+            let report = bad_links_and_anchors
                 .entry((!had_sources, broken_link.link.path))
-                .or_insert_with(|| (BTreeSet::new(), BTreeSet::new()));
+                .or_insert_with(Default::default);
 
             if broken_link.hard_404 {
-                bad_links
+                &mut report.bad_links
             } else {
-                bad_anchors
+                &mut report.bad_anchors
             }
-            .insert((None, broken_link.link.href));
+            .insert((None, href));
+        }
+    }
+
+    let mut diagnostic_counts = BTreeMap::new();
+    let mut site_diagnostics = html_result
+        .collector
+        .redirect_diagnostics(server_config.profile, site_checks.max_redirect_chain);
+
+    if site_checks.check_link_relations {
+        site_diagnostics.extend(
+            html_result
+                .collector
+                .relation_diagnostics(server_config.profile),
+        );
+    }
+
+    if site_checks.check_case {
+        site_diagnostics.extend(
+            html_result
+                .collector
+                .case_diagnostics(server_config.profile),
+        );
+    }
+
+    if let Some(form) = site_checks.unicode_form {
+        site_diagnostics.extend(
+            html_result
+                .collector
+                .unicode_diagnostics(server_config.profile, form),
+        );
+    }
+
+    if site_checks.check_sitemap {
+        site_diagnostics.extend(
+            html_result
+                .collector
+                .sitemap_diagnostics(server_config.profile),
+        );
+    }
+
+    for diagnostic in html_result
+        .collector
+        .diagnostics()
+        .iter()
+        .chain(&site_diagnostics)
+    {
+        *diagnostic_counts.entry(diagnostic.kind).or_insert(0) += 1;
+
+        let report = bad_links_and_anchors
+            .entry((true, diagnostic.path.clone()))
+            .or_insert_with(Default::default);
+
+        if diagnostic.kind.is_error() {
+            &mut report.errors
+        } else {
+            &mut report.warnings
         }
+        .insert((None, diagnostic.message.clone()));
     }
 
     // _is_raw_file is an unused parameter that is only there to control iteration order over keys.
     // Sort markdown files to the start since otherwise the less valuable annotations on not
     // checked in files fill up the limit on annotations (tested manually, seems to be 10 right
     // now).
-    for ((_is_raw_file, filepath), (bad_links, bad_anchors)) in bad_links_and_anchors {
+    for ((_is_raw_file, filepath), report) in bad_links_and_anchors {
         println!("{}", filepath.display());
 
-        for (lineno, href) in &bad_links {
+        for (lineno, href) in &report.bad_links {
             print_href_error("error: bad link", href, *lineno);
         }
 
-        for (lineno, href) in &bad_anchors {
+        for (lineno, href) in &report.bad_anchors {
             print_href_error("error: bad link", href, *lineno);
         }
 
+        for (_, message) in &report.errors {
+            println!("  error: {}", message);
+        }
+
+        for (_, message) in &report.warnings {
+            println!("  warning: {}", message);
+        }
+
         if github_actions {
-            if !bad_links.is_empty() {
-                print_github_actions_href_list("bad links", &*filepath, &bad_links)?;
+            if !report.bad_links.is_empty() {
+                print_github_actions_href_list("error", "bad links", &filepath, &report.bad_links)?;
+            }
+
+            if !report.bad_anchors.is_empty() {
+                print_github_actions_href_list(
+                    "error",
+                    "bad anchors",
+                    &filepath,
+                    &report.bad_anchors,
+                )?;
+            }
+
+            if !report.errors.is_empty() {
+                print_github_actions_href_list("error", "errors", &filepath, &report.errors)?;
             }
 
-            if !bad_anchors.is_empty() {
-                print_github_actions_href_list("bad anchors", &*filepath, &bad_anchors)?;
+            if !report.warnings.is_empty() {
+                print_github_actions_href_list("warning", "warnings", &filepath, &report.warnings)?;
             }
         }
 
@@ -251,18 +608,22 @@ where
 
     println!("Found {} bad links", bad_links_count);
 
-    if check_anchors {
+    if options.check_anchors {
         println!("Found {} bad anchors", bad_anchors_count);
     }
 
+    for (kind, count) in &diagnostic_counts {
+        println!("Found {} {}", count, kind);
+    }
+
     // We're about to exit the program and leaking the memory is faster than running drop
     mem::forget(html_result);
 
-    if bad_links_count > 0 {
+    if bad_links_count > 0 || diagnostic_counts.keys().any(|kind| kind.is_error()) {
         process::exit(1);
     }
 
-    if bad_anchors_count > 0 {
+    if bad_anchors_count > 0 || diagnostic_counts.keys().any(|kind| kind.fails_run()) {
         process::exit(2);
     }
 
@@ -278,16 +639,23 @@ fn print_href_error(message: &'static str, href: &str, lineno: Option<usize>) {
 }
 
 fn print_github_actions_href_list(
+    level: &'static str,
     message: &'static str,
     filepath: &Path,
     hrefs: &BTreeSet<(Option<usize>, String)>,
 ) -> Result<(), Error> {
+    // Files in an archive don't exist on disk, so they keep the path they are reported under.
+    let filepath = filepath
+        .canonicalize()
+        .unwrap_or_else(|_| filepath.to_owned());
+
     let mut prev_lineno = None;
     for (i, (lineno, href)) in hrefs.iter().enumerate() {
         if prev_lineno != *lineno || i == 0 {
             print!(
-                "\n::error file={},line={}::{}:",
-                filepath.canonicalize()?.display(),
+                "\n::{} file={},line={}::{}:",
+                level,
+                filepath.display(),
                 lineno.unwrap_or(1),
                 message,
             );
@@ -323,9 +691,14 @@ fn dump_paragraphs(path: PathBuf) -> Result<(), Error> {
                 .collect()
         }
         Some(x) if HTML_FILES.contains(&x) => {
-            let document = Document::new(Path::new(""), &path);
+            let document = Document::new(Path::new(""), &path)?;
             document
-                .links::<DebugParagraphWalker<ParagraphHasher>>(&mut doc_buf, false, true)?
+                .links::<DebugParagraphWalker<ParagraphHasher>>(
+                    &mut doc_buf,
+                    &ServerConfig::default(),
+                    &CheckOptions::default(),
+                    true,
+                )?
                 .filter_map(|link| Some((link.into_paragraph()?, None)))
                 .collect()
         }
@@ -349,94 +722,164 @@ struct HtmlResult<C> {
     file_count: usize,
 }
 
-fn walk_files(
-    base_path: &Path,
-) -> Result<impl ParallelIterator<Item = jwalk::DirEntry<((), ())>>, Error> {
-    let entries = WalkDir::new(&base_path)
-        .sort(true) // helps branch predictor (?)
-        .process_read_dir(|_, _, _, children| {
-            children.retain(|dir_entry_result| {
-                let entry = match dir_entry_result.as_ref() {
-                    Ok(x) => x,
-                    Err(_) => return true,
-                };
+fn is_html_file(extension: Option<&str>, options: &CheckOptions) -> bool {
+    matches!(
+        extension,
+        Some(x) if HTML_FILES.contains(&x)
+            || options.html_extensions.iter().any(|e| e == x)
+            || (options.epub && XHTML_FILES.contains(&x))
+    )
+}
 
-                let file_type = entry.file_type();
+/// Whether a document might be HTML without an extension that says so, see `--sniff-html`.
+fn is_sniffed_file(document: &Document, options: &CheckOptions) -> bool {
+    options.sniff_html
+        && document.extension().is_none()
+        && !REDIRECT_FILES.contains(&document.href().0)
+}
 
-                if file_type.is_dir() {
-                    // need to retain, otherwise jwalk won't recurse
-                    return true;
-                }
-                if file_type.is_symlink() {
-                    return false;
-                }
+/// Read the visible text of the HTML pages with the given hrefs, for checking text fragments.
+fn extract_page_texts(
+    base_path: &Path,
+    server_config: &ServerConfig,
+    options: &CheckOptions,
+    hrefs: &BTreeSet<String>,
+) -> Result<BTreeMap<String, String>, Error> {
+    if let Some(archive) = Archive::from_base_path(base_path) {
+        let base_path = archive.base_path();
+        let paths = archive.file_paths()?;
+        let mut doc_buf = DocumentBuffers::default();
+        let mut texts = BTreeMap::new();
+
+        archive.for_each_file(|path, read| {
+            // No link can point to a file without a document, and it is reported while collecting
+            // links.
+            let document = match new_document(&base_path, &path, server_config, &paths) {
+                Ok(document) => document,
+                Err(_) => return Ok(()),
+            };
 
-                if !file_type.is_file() {
-                    return false;
-                }
+            if let Some((href, text)) =
+                extract_page_text(document, || Ok(read), options, hrefs, &mut doc_buf)?
+            {
+                texts.insert(href, text);
+            }
+            Ok(())
+        })?;
 
-                true
-            });
-        })
-        .into_iter()
-        .filter_map(|entry| {
-            let entry = match entry {
-                Ok(x) => x,
-                Err(e) => return Some(Err(e)),
+        return Ok(texts);
+    }
+
+    let walk = walk_files(base_path, Symlinks::from_options(options))?;
+    let paths = walk.paths();
+
+    let texts: Result<Vec<_>, Error> = walk
+        .into_par_iter()
+        // The text of an alias is that of the file it points to.
+        .filter(|file| file.alias_of.is_none())
+        .map_init(DocumentBuffers::default, |doc_buf, file| {
+            let path = file.path;
+            let document = match new_document(base_path, &path, server_config, &paths) {
+                Ok(document) => document,
+                Err(_) => return Ok(None),
             };
 
-            if entry.file_type().is_dir() {
-                None
-            } else {
-                Some(Ok(entry))
-            }
+            extract_page_text(document, || fs::File::open(&path), options, hrefs, doc_buf)
         })
-        // XXX: cannot use par_bridge because of https://github.com/rayon-rs/rayon/issues/690
-        .collect::<Result<Vec<_>, _>>()?;
+        .filter_map(Result::transpose)
+        .collect();
+
+    Ok(texts?.into_iter().collect())
+}
+
+fn extract_page_text<R: Read>(
+    document: Document,
+    open: impl FnOnce() -> io::Result<R>,
+    options: &CheckOptions,
+    hrefs: &BTreeSet<String>,
+    doc_buf: &mut DocumentBuffers,
+) -> Result<Option<(String, String)>, Error> {
+    let is_html = is_html_file(document.extension(), options);
+
+    if !(is_html || is_sniffed_file(&document, options))
+        || !hrefs.contains(document.href().0)
+        || document.is_shadowed()
+    {
+        return Ok(None);
+    }
+
+    let context = || format!("Failed to read file {}", document.path.display());
+    let mut read = document.decompress(open().with_context(context)?);
+
+    if !is_html {
+        let (is_html, sniffed) = sniff_html(read).with_context(context)?;
+        if !is_html {
+            return Ok(None);
+        }
+        read = Box::new(sniffed);
+    }
 
-    // Minimize amount of LinkCollector instances created. This impacts parallelism but
-    // `LinkCollector::merge` is rather slow.
-    let min_len = entries.len() / rayon::current_num_threads();
-    Ok(entries.into_par_iter().with_min_len(min_len))
+    let text = document
+        .text_from_read(doc_buf, read)
+        .with_context(context)?;
+    doc_buf.reset();
+
+    Ok(Some((document.href().0.to_owned(), text)))
 }
 
 fn extract_html_links<C: LinkCollector<P::Paragraph>, P: ParagraphWalker>(
     base_path: &Path,
-    check_anchors: bool,
+    server_config: &ServerConfig,
+    options: &CheckOptions,
     get_paragraphs: bool,
 ) -> Result<HtmlResult<C>, Error> {
-    let result: Result<_, Error> = walk_files(base_path)?
+    if let Some(archive) = Archive::from_base_path(base_path) {
+        return extract_archive_links::<C, P>(&archive, server_config, options, get_paragraphs);
+    }
+
+    let mut walk = walk_files(base_path, Symlinks::from_options(options))?;
+    let loops = mem::take(&mut walk.loops);
+    let paths = walk.paths();
+
+    let result: Result<_, Error> = walk
+        .into_par_iter()
         .try_fold(
             || (DocumentBuffers::default(), C::new(), 0, 0),
-            |(mut doc_buf, mut collector, mut documents_count, mut file_count), entry| {
-                let path = entry.path();
-                let document = Document::new(base_path, &path);
-
-                collector.ingest(Link::Defines(DefinedLink {
-                    href: document.href(),
-                }));
+            |(mut doc_buf, mut collector, mut documents_count, mut file_count), file| {
                 file_count += 1;
 
-                if !document
-                    .path
-                    .extension()
-                    .and_then(|extension| Some(HTML_FILES.contains(&extension.to_str()?)))
-                    .unwrap_or(false)
-                {
+                let document = match open_document(
+                    &mut collector,
+                    base_path,
+                    file.path,
+                    server_config,
+                    &paths,
+                ) {
+                    Some(document) => document,
+                    None => return Ok((doc_buf, collector, documents_count, file_count)),
+                };
+
+                if let Some(alias_of) = file.alias_of {
+                    if let Some(original) =
+                        open_document(&mut collector, base_path, alias_of, server_config, &paths)
+                    {
+                        ingest_alias(&mut collector, &document, &original, options);
+                    }
                     return Ok((doc_buf, collector, documents_count, file_count));
                 }
 
-                for link in document
-                    .links::<P>(&mut doc_buf, check_anchors, get_paragraphs)
-                    .with_context(|| format!("Failed to read file {}", document.path.display()))?
-                {
-                    collector.ingest(link);
+                if ingest_file::<_, P, _>(
+                    &mut collector,
+                    &mut doc_buf,
+                    &document,
+                    || fs::File::open(&*document.path),
+                    server_config,
+                    options,
+                    get_paragraphs,
+                )? {
+                    documents_count += 1;
                 }
 
-                doc_buf.reset();
-
-                documents_count += 1;
-
                 Ok((doc_buf, collector, documents_count, file_count))
             },
         )
@@ -456,7 +899,77 @@ fn extract_html_links<C: LinkCollector<P::Paragraph>, P: ParagraphWalker>(
             },
         );
 
-    let (collector, documents_count, file_count) = result?;
+    let (mut collector, documents_count, file_count) = result?;
+
+    for path in loops {
+        let target = fs::read_link(&path)?;
+        collector.ingest(Link::Reports(Diagnostic {
+            kind: DiagnosticKind::SymlinkLoop,
+            message: format!(
+                "symlink to {} loops back to a parent folder",
+                target.display()
+            ),
+            path: Arc::new(path),
+        }));
+    }
+
+    Ok(HtmlResult {
+        collector,
+        documents_count,
+        file_count,
+    })
+}
+
+/// Like `extract_html_links`, for a site stored in an archive. Archives are read from start to
+/// end, so their files are not read in parallel.
+fn extract_archive_links<C: LinkCollector<P::Paragraph>, P: ParagraphWalker>(
+    archive: &Archive,
+    server_config: &ServerConfig,
+    options: &CheckOptions,
+    get_paragraphs: bool,
+) -> Result<HtmlResult<C>, Error> {
+    let base_path = archive.base_path();
+    let paths = archive.file_paths()?;
+    let mut doc_buf = DocumentBuffers::default();
+    let mut collector = C::new();
+    let mut documents_count = 0;
+    let mut file_count = 0;
+
+    let mut has_container = false;
+
+    archive.for_each_file(|path, read| {
+        file_count += 1;
+
+        let document = match open_document(&mut collector, &base_path, path, server_config, &paths)
+        {
+            Some(document) => document,
+            None => return Ok(()),
+        };
+
+        has_container |= document.href().0 == EPUB_CONTAINER_HREF;
+
+        if ingest_file::<_, P, _>(
+            &mut collector,
+            &mut doc_buf,
+            &document,
+            || Ok(read),
+            server_config,
+            options,
+            get_paragraphs,
+        )? {
+            documents_count += 1;
+        }
+
+        Ok(())
+    })?;
+
+    if options.epub && archive.is_epub() && !has_container {
+        collector.ingest(Link::Reports(Diagnostic {
+            kind: DiagnosticKind::BadPackage,
+            path: Arc::new(archive.path().to_owned()),
+            message: format!("{} is missing", EPUB_CONTAINER_HREF),
+        }));
+    }
 
     Ok(HtmlResult {
         collector,
@@ -465,21 +978,224 @@ fn extract_html_links<C: LinkCollector<P::Paragraph>, P: ParagraphWalker>(
     })
 }
 
+/// Create the document for a file among the given `paths` of all files of the site, which decide
+/// which variant of a precompressed file is read.
+fn new_document(
+    base_path: &Path,
+    path: &Path,
+    server_config: &ServerConfig,
+    paths: &BTreeSet<PathBuf>,
+) -> Result<Document, Error> {
+    let mut document = Document::with_profile(base_path, path, server_config.profile)?;
+    document.find_variants(|path| paths.contains(path));
+    Ok(document)
+}
+
+/// Create the document for a file like `new_document`, or report why there is none.
+fn open_document<C: LinkCollector<T>, T: Send>(
+    collector: &mut C,
+    base_path: &Path,
+    path: PathBuf,
+    server_config: &ServerConfig,
+    paths: &BTreeSet<PathBuf>,
+) -> Option<Document> {
+    match new_document(base_path, &path, server_config, paths) {
+        Ok(document) => Some(document),
+        Err(error) => {
+            collector.ingest(Link::Reports(Diagnostic {
+                kind: DiagnosticKind::InvalidUtf8,
+                path: Arc::new(path),
+                message: error.to_string(),
+            }));
+            None
+        }
+    }
+}
+
+/// Define the href of a file that `--alias-symlinks` found to be a symlink to `original`, without
+/// reading it again. Anchors on it are checked on `original` as if it redirected there.
+fn ingest_alias<C: LinkCollector<T>, T: Send>(
+    collector: &mut C,
+    document: &Document,
+    original: &Document,
+    options: &CheckOptions,
+) {
+    let defined_link = DefinedLink {
+        href: document.href(),
+    };
+
+    if is_html_file(document.extension(), options) {
+        collector.ingest(Link::DefinesPage(defined_link));
+    } else {
+        collector.ingest(Link::Defines(defined_link));
+    }
+
+    collector.ingest(Link::Redirects(Redirect {
+        source: document.href(),
+        destination: original.href(),
+        path: document.path.clone(),
+    }));
+}
+
+/// Define the href of a file and, depending on its type, read the links in it. Returns whether
+/// the file was read as an HTML page.
+fn ingest_file<C: LinkCollector<P::Paragraph>, P: ParagraphWalker, R: Read>(
+    collector: &mut C,
+    doc_buf: &mut DocumentBuffers,
+    document: &Document,
+    open: impl FnOnce() -> io::Result<R>,
+    server_config: &ServerConfig,
+    options: &CheckOptions,
+    get_paragraphs: bool,
+) -> Result<bool, Error> {
+    let extension = document.extension();
+    let context = || format!("Failed to read file {}", document.path.display());
+
+    if is_sniffed_file(document, options) {
+        let (is_html, read) =
+            sniff_html(document.decompress(open().with_context(context)?)).with_context(context)?;
+
+        let defined_link = DefinedLink {
+            href: document.href(),
+        };
+
+        if !is_html {
+            collector.ingest(Link::Defines(defined_link));
+            return Ok(false);
+        }
+
+        collector.ingest(Link::DefinesPage(defined_link));
+
+        for link in document
+            .links_from_read::<_, P>(doc_buf, read, server_config, options, get_paragraphs)
+            .with_context(context)?
+        {
+            collector.ingest(link);
+        }
+
+        doc_buf.reset();
+
+        return Ok(true);
+    }
+
+    let defined_link = DefinedLink {
+        href: document.href(),
+    };
+
+    let is_html = is_html_file(extension, options);
+
+    if is_html {
+        collector.ingest(Link::DefinesPage(defined_link));
+    } else {
+        collector.ingest(Link::Defines(defined_link));
+    }
+
+    if let Some(href) = document.compressed_href() {
+        collector.ingest(Link::Defines(DefinedLink { href }));
+    }
+
+    // Otherwise the same links would be reported twice.
+    if document.is_shadowed() {
+        return Ok(false);
+    }
+
+    let open = || open().map(|read| document.decompress(read));
+
+    let check_anchors = options.check_anchors;
+
+    let is_epub_file = document.href().0 == EPUB_CONTAINER_HREF
+        || matches!(extension, Some(x) if EPUB_FILES.contains(&x));
+
+    match extension {
+        _ if is_html => {
+            for link in document
+                .links_from_read::<_, P>(doc_buf, open()?, server_config, options, get_paragraphs)
+                .with_context(context)?
+            {
+                collector.ingest(link);
+            }
+        }
+        Some(x) if SVG_FILES.contains(&x) => {
+            for link in document
+                .links_from_read::<_, P>(doc_buf, open()?, server_config, options, false)
+                .with_context(context)?
+            {
+                collector.ingest(link);
+            }
+        }
+        Some(x) if PDF_FILES.contains(&x) && check_anchors && options.check_pdf_anchors => {
+            for link in document
+                .pdf_links_from_read::<_, P>(doc_buf, open()?)
+                .with_context(context)?
+            {
+                collector.ingest(link);
+            }
+        }
+        Some(x) if CSS_FILES.contains(&x) => {
+            for link in document
+                .css_links_from_read::<_, P>(doc_buf, open()?, server_config, check_anchors)
+                .with_context(context)?
+            {
+                collector.ingest(link);
+            }
+        }
+        _ if is_epub_file && options.epub => {
+            for link in document
+                .epub_links_from_read::<_, P>(doc_buf, open()?, server_config, check_anchors)
+                .with_context(context)?
+            {
+                collector.ingest(link);
+            }
+        }
+        Some(x) if XML_FILES.contains(&x) => {
+            for link in document
+                .feed_links_from_read::<_, P>(doc_buf, open()?, server_config, check_anchors)
+                .with_context(context)?
+            {
+                collector.ingest(link);
+            }
+        }
+        _ if document.href().0 == "robots.txt" => {
+            for link in document
+                .feed_links_from_read::<_, P>(doc_buf, open()?, server_config, check_anchors)
+                .with_context(context)?
+            {
+                collector.ingest(link);
+            }
+        }
+        _ if REDIRECT_FILES.contains(&document.href().0) => {
+            for link in document
+                .redirect_links_from_read::<_, P>(doc_buf, open()?, server_config, check_anchors)
+                .with_context(context)?
+            {
+                collector.ingest(link);
+            }
+        }
+        _ => return Ok(false),
+    }
+
+    doc_buf.reset();
+
+    // Only HTML pages count as documents, stylesheets and the like are just files with links.
+    Ok(is_html)
+}
+
 type MarkdownResult<P> = BTreeMap<P, Vec<(DocumentSource, usize)>>;
 
 fn extract_markdown_paragraphs<P: ParagraphWalker>(
-    sources_path: &Path,
+    sources: &Sources,
 ) -> Result<MarkdownResult<P::Paragraph>, Error> {
-    let results: Vec<Result<_, Error>> = walk_files(sources_path)?
-        .try_fold(Vec::new, |mut paragraphs, entry| {
-            let source = DocumentSource::new(entry.path());
-
-            if !source
-                .path
-                .extension()
-                .and_then(|extension| Some(MARKDOWN_FILES.contains(&extension.to_str()?)))
-                .unwrap_or(false)
-            {
+    let results: Vec<Result<_, Error>> = walk_files(&sources.path, Symlinks::Skip)?
+        .into_par_iter()
+        .try_fold(Vec::new, |mut paragraphs, file| {
+            let source = DocumentSource::new(file.path);
+
+            let is_markdown = matches!(
+                source.path.extension().and_then(|extension| extension.to_str()),
+                Some(x) if MARKDOWN_FILES.contains(&x) || sources.extensions.iter().any(|e| e == x)
+            );
+
+            if !is_markdown {
                 return Ok(paragraphs);
             }
 
@@ -509,11 +1225,21 @@ fn extract_markdown_paragraphs<P: ParagraphWalker>(
 
 fn match_all_paragraphs(base_path: PathBuf, sources_path: PathBuf) -> Result<(), Error> {
     println!("Reading files");
-    let html_result =
-        extract_html_links::<UsedLinkCollector<_>, ParagraphHasher>(&base_path, true, true)?;
+    let html_result = extract_html_links::<UsedLinkCollector<_>, ParagraphHasher>(
+        &base_path,
+        &ServerConfig::default(),
+        &CheckOptions {
+            check_anchors: true,
+            ..Default::default()
+        },
+        true,
+    )?;
 
     println!("Reading source files");
-    let paragraps_to_sourcefile = extract_markdown_paragraphs::<ParagraphHasher>(&sources_path)?;
+    let paragraps_to_sourcefile = extract_markdown_paragraphs::<ParagraphHasher>(&Sources {
+        path: sources_path,
+        extensions: Vec::new(),
+    })?;
 
     println!("Calculating");
     let mut total_links = 0;
@@ -596,24 +1322,1353 @@ Found 1 bad links
     }
 
     #[test]
-    fn test_dead_anchor() {
+    fn test_dead_link_css() {
         let site = assert_fs::TempDir::new().unwrap();
         site.child("index.html")
-            .write_str("<a href=bar.html#goo>")
+            .write_str("<link rel=stylesheet href=css/main.css>")
+            .unwrap();
+        site.child("css/main.css")
+            .write_str("@font-face { src: url(../fonts/inter.woff2) }")
             .unwrap();
-        site.child("bar.html").touch().unwrap();
         let mut cmd = Command::cargo_bin("hyperlink").unwrap();
-        cmd.current_dir(site.path()).arg(".").arg("--check-anchors");
+        cmd.current_dir(site.path()).arg(".");
 
-        cmd.assert().failure().code(2).stdout(
+        cmd.assert().failure().code(1).stdout(
             predicate::str::is_match(
                 r#"^Reading files
-Checking 1 links from 2 files \(2 documents\)
+Checking 2 links from 2 files \(1 documents\)
+\..css.main\.css
+  error: bad link /fonts/inter.woff2
+
+Found 1 bad links
+"#,
+            )
+            .unwrap(),
+        );
+        site.close().unwrap();
+    }
+
+    #[test]
+    fn test_dead_link_site_url() {
+        let site = assert_fs::TempDir::new().unwrap();
+        site.child("index.html")
+            .write_str(
+                "<a href=https://example.com/guide/missing><a href=https://www.example.com/>",
+            )
+            .unwrap();
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path())
+            .arg(".")
+            .arg("--site-url")
+            .arg("https://example.com/")
+            .arg("--site-url")
+            .arg("https://www.example.com");
+
+        cmd.assert().failure().code(1).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 2 links from 1 files \(1 documents\)
 \..index\.html
-  error: bad link /bar.html#goo
+  error: bad link /guide/missing
 
-Found 0 bad links
-Found 1 bad anchors
+Found 1 bad links
+"#,
+            )
+            .unwrap(),
+        );
+        site.close().unwrap();
+    }
+
+    #[test]
+    fn test_path_prefix() {
+        let site = assert_fs::TempDir::new().unwrap();
+        site.child("index.html")
+            .write_str("<a href=/docs/guide/><a href=/guide/><a href=/docs/missing.html>")
+            .unwrap();
+        site.child("guide/index.html").touch().unwrap();
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path())
+            .arg(".")
+            .arg("--path-prefix")
+            .arg("/docs/");
+
+        cmd.assert().failure().code(1).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 2 links from 2 files \(2 documents\)
+\..index\.html
+  error: bad link /missing.html
+  error: link /guide/ does not start with path prefix /docs
+
+Found 1 bad links
+Found 1 links without path prefix
+$"#,
+            )
+            .unwrap(),
+        );
+        site.close().unwrap();
+    }
+
+    #[test]
+    fn test_server_profile() {
+        let site = assert_fs::TempDir::new().unwrap();
+        site.child("index.html")
+            .write_str("<a href=about><a href=guide><a href=guide/#intro><a href=about/>")
+            .unwrap();
+        site.child("about.html").touch().unwrap();
+        site.child("guide/index.html")
+            .write_str("<h2 id=intro>")
+            .unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path())
+            .arg(".")
+            .arg("--check-anchors")
+            .arg("--server-profile")
+            .arg("github-pages");
+
+        cmd.assert().failure().code(1).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 4 links from 3 files \(3 documents\)
+\..index\.html
+  error: bad link /about/
+
+Found 1 bad links
+Found 0 bad anchors
+$"#,
+            )
+            .unwrap(),
+        );
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path())
+            .arg(".")
+            .arg("--server-profile")
+            .arg("strict");
+
+        cmd.assert().failure().code(1).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 4 links from 3 files \(3 documents\)
+\..index\.html
+  error: bad link /about
+  error: bad link /about/
+  error: bad link /guide
+
+Found 3 bad links
+$"#,
+            )
+            .unwrap(),
+        );
+
+        site.close().unwrap();
+    }
+
+    #[test]
+    fn test_redirects() {
+        let site = assert_fs::TempDir::new().unwrap();
+        site.child("index.html")
+            .write_str("<a href=/old-page><a href=/blog/2021/hello><a href=/missing>")
+            .unwrap();
+        site.child("_redirects")
+            .write_str("/old-page /new-page 301\n/blog/* /news/:splat\n")
+            .unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path()).arg(".");
+
+        cmd.assert().failure().code(1).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 4 links from 2 files \(1 documents\)
+\.._redirects
+  error: bad link /new-page
+
+\..index\.html
+  error: bad link /missing
+
+Found 2 bad links
+$"#,
+            )
+            .unwrap(),
+        );
+
+        // Configs with syntax errors are reported, the other files are still checked.
+        site.child("netlify.toml")
+            .write_str("[[redirects]\nfrom = \"/a\"\n")
+            .unwrap();
+        site.child("vercel.json")
+            .write_str(r#"{"redirects": [}"#)
+            .unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path()).arg(".");
+
+        cmd.assert().failure().code(1).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 4 links from 4 files \(1 documents\)
+\.._redirects
+  error: bad link /new-page
+
+\..index\.html
+  error: bad link /missing
+
+\..netlify\.toml
+  warning: cannot read redirects, ignoring them: .+
+
+\..vercel\.json
+  warning: cannot read redirects, ignoring them: .+
+
+Found 2 bad links
+Found 2 unreadable files
+$"#,
+            )
+            .unwrap(),
+        );
+
+        site.close().unwrap();
+    }
+
+    #[test]
+    fn test_meta_refresh() {
+        let site = assert_fs::TempDir::new().unwrap();
+        site.child("index.html")
+            .write_str("<a href=old.html#intro><a href=old.html#missing><a href=loop-a.html>")
+            .unwrap();
+        site.child("old.html")
+            .write_str(r#"<meta http-equiv="refresh" content="0; url=older.html">"#)
+            .unwrap();
+        site.child("older.html")
+            .write_str(r#"<meta content="0;URL='new.html'" http-equiv=Refresh>"#)
+            .unwrap();
+        site.child("new.html").write_str("<h2 id=intro>").unwrap();
+        site.child("loop-a.html")
+            .write_str(r#"<meta http-equiv="refresh" content="0; url=loop-b.html">"#)
+            .unwrap();
+        site.child("loop-b.html")
+            .write_str(r#"<meta http-equiv="refresh" content="0; url=loop-a.html">"#)
+            .unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path())
+            .arg(".")
+            .arg("--check-anchors")
+            .arg("--max-redirect-chain")
+            .arg("1");
+
+        cmd.assert().failure().code(1).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 7 links from 6 files \(6 documents\)
+\..index\.html
+  error: bad link /old\.html#missing
+
+\..loop-a\.html
+  error: redirect loop: /loop-a\.html -> /loop-b\.html -> /loop-a\.html
+
+\..loop-b\.html
+  error: redirect loop: /loop-b\.html -> /loop-a\.html -> /loop-b\.html
+
+\..old\.html
+  warning: redirect chain of 2 hops: /old\.html -> /older\.html -> /new\.html
+
+Found 0 bad links
+Found 1 bad anchors
+Found 2 redirect loops
+Found 1 long redirect chains
+$"#,
+            )
+            .unwrap(),
+        );
+
+        site.close().unwrap();
+    }
+
+    #[test]
+    fn test_sitemap_and_feeds() {
+        let site = assert_fs::TempDir::new().unwrap();
+        site.child("index.html")
+            .write_str("<a href=about.html>")
+            .unwrap();
+        site.child("about.html").touch().unwrap();
+        site.child("old.html")
+            .write_str(r#"<meta http-equiv="refresh" content="0; url=about.html">"#)
+            .unwrap();
+        site.child("sitemap.xml")
+            .write_str(
+                r#"<urlset>
+                <url><loc>https://example.com/</loc></url>
+                <url><loc>https://example.com/missing.html</loc></url>
+                </urlset>"#,
+            )
+            .unwrap();
+        site.child("robots.txt")
+            .write_str("Sitemap: https://example.com/sitemap.xml")
+            .unwrap();
+        site.child("feed.xml")
+            .write_str(
+                r#"<rss><channel><link>https://example.com/</link>
+                <item><link>https://example.com/posts/gone.html</link></item>
+                </channel></rss>"#,
+            )
+            .unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path())
+            .arg(".")
+            .arg("--site-url")
+            .arg("https://example.com")
+            .arg("--check-sitemap");
+
+        cmd.assert().failure().code(1).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 7 links from 6 files \(3 documents\)
+\..feed\.xml
+  error: bad link /posts/gone\.html
+
+\..sitemap\.xml
+  error: bad link /missing\.html
+  warning: page /about\.html is not listed in the sitemap
+
+Found 2 bad links
+Found 1 pages missing from sitemap
+$"#,
+            )
+            .unwrap(),
+        );
+
+        site.close().unwrap();
+    }
+
+    #[test]
+    fn test_malformed_feed() {
+        let site = assert_fs::TempDir::new().unwrap();
+        site.child("index.html")
+            .write_str(r#"<a href="missing.html"></a>"#)
+            .unwrap();
+        site.child("feed.xml")
+            .write_str(
+                "<rss><channel><link>/gone.html</link>
+                <item><link>/posts/?page=1&sort=new</link></item>
+                <item><link>/also-gone.html</link></item></channel></rss>",
+            )
+            .unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path()).arg(".");
+
+        cmd.assert().failure().code(1).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 2 links from 2 files \(1 documents\)
+\..feed\.xml
+  error: bad link /gone\.html
+  warning: malformed XML, later URLs are not checked: .+
+
+\..index\.html
+  error: bad link /missing\.html
+
+Found 2 bad links
+Found 1 unreadable files
+$"#,
+            )
+            .unwrap(),
+        );
+
+        site.close().unwrap();
+    }
+
+    #[test]
+    fn test_link_relations() {
+        let site = assert_fs::TempDir::new().unwrap();
+        site.child("en.html")
+            .write_str(
+                r#"<link rel=canonical href=en.html>
+                <link rel=alternate hreflang=de href=de.html>
+                <link rel=alternate hreflang=fr href=fr.html>
+                <link rel=next href=page2.html>"#,
+            )
+            .unwrap();
+        site.child("de.html")
+            .write_str("<link rel=alternate hreflang=en href=en.html>")
+            .unwrap();
+        site.child("fr.html")
+            .write_str("<link rel=canonical href=de.html>")
+            .unwrap();
+        site.child("page2.html")
+            .write_str("<link rel=canonical href=fr.html><a rel=prev href=de.html>")
+            .unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path())
+            .arg(".")
+            .arg("--check-link-relations");
+
+        cmd.assert().failure().code(2).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 8 links from 4 files \(4 documents\)
+\..en\.html
+  warning: hreflang alternate /fr\.html \(fr\) does not link back to /en\.html
+  warning: next page /page2\.html has /de\.html as previous page instead of /en\.html
+
+\..page2\.html
+  warning: canonical link /fr\.html is not canonical itself, it points to /de\.html
+  warning: previous page /de\.html has no next page, expected /page2\.html
+
+Found 0 bad links
+Found 1 bad canonical links
+Found 1 non-reciprocal hreflang alternates
+Found 2 inconsistent prev/next links
+$"#,
+            )
+            .unwrap(),
+        );
+
+        site.close().unwrap();
+    }
+
+    #[test]
+    fn test_idrefs() {
+        let site = assert_fs::TempDir::new().unwrap();
+        site.child("index.html")
+            .write_str(
+                r#"<label for=name>Name</label><input id=name list=names>
+                <button aria-controls="menu nav">Menu</button><nav id=nav></nav>"#,
+            )
+            .unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path()).arg(".");
+
+        cmd.assert().success();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path()).arg(".").arg("--check-idrefs");
+
+        cmd.assert().failure().code(2).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 0 links from 1 files \(1 documents\)
+\..index\.html
+  warning: <button aria-controls="menu nav"> refers to missing id "menu"
+  warning: <input list="names"> refers to missing id "names"
+
+Found 0 bad links
+Found 2 bad id references
+$"#,
+            )
+            .unwrap(),
+        );
+
+        site.close().unwrap();
+    }
+
+    #[test]
+    fn test_duplicate_anchors() {
+        let site = assert_fs::TempDir::new().unwrap();
+        site.child("index.html")
+            .write_str(
+                r#"<h2 id=intro>Intro</h2><a id=usage name=usage></a><a name=intro></a>
+                <a href=#intro>"#,
+            )
+            .unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path()).arg(".").arg("--check-anchors");
+
+        cmd.assert().success();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path())
+            .arg(".")
+            .arg("--check-anchors")
+            .arg("--check-duplicate-anchors");
+
+        cmd.assert().failure().code(2).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 1 links from 1 files \(1 documents\)
+\..index\.html
+  warning: anchor #intro is defined by both <h2 id="intro"> and <a name="intro">
+
+Found 0 bad links
+Found 0 bad anchors
+Found 1 duplicate anchors
+$"#,
+            )
+            .unwrap(),
+        );
+
+        site.close().unwrap();
+    }
+
+    #[test]
+    fn test_svg() {
+        let site = assert_fs::TempDir::new().unwrap();
+        site.child("index.html")
+            .write_str(
+                r##"<svg><use href="icons.svg#icon-search"/></svg>
+                <svg><use xlink:href="icons.svg#icon-close"/></svg>
+                <img src="diagram.svg">"##,
+            )
+            .unwrap();
+        site.child("icons.svg")
+            .write_str(
+                r#"<svg xmlns="http://www.w3.org/2000/svg">
+                <symbol id="icon-search" viewBox="0 0 16 16"/>
+                </svg>"#,
+            )
+            .unwrap();
+        site.child("diagram.svg")
+            .write_str(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink">
+                <a xlink:href="index.html"><text>Home</text></a>
+                <a href="missing.html"><text>Gone</text></a>
+                </svg>"#,
+            )
+            .unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path()).arg(".").arg("--check-anchors");
+
+        cmd.assert().failure().code(1).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 5 links from 3 files \(1 documents\)
+\..diagram\.svg
+  error: bad link /missing\.html
+
+\..index\.html
+  error: bad link /icons\.svg#icon-close
+
+Found 1 bad links
+Found 1 bad anchors
+$"#,
+            )
+            .unwrap(),
+        );
+
+        site.close().unwrap();
+    }
+
+    #[test]
+    fn test_pdf_anchors() {
+        let site = assert_fs::TempDir::new().unwrap();
+        site.child("index.html")
+            .write_str(
+                r#"<a href="manual.pdf#page=2&zoom=200"></a>
+                <a href="manual.pdf#page=3"></a>
+                <a href="manual.pdf#nameddest=intro"></a>
+                <a href="manual.pdf#intro"></a>
+                <a href="manual.pdf#outro"></a>
+                <a href="manual.pdf#view=FitH"></a>
+                <a href="broken.pdf#page=1"></a>"#,
+            )
+            .unwrap();
+        site.child("manual.pdf")
+            .write_binary(&crate::html::build_test_pdf(2, &["intro"], &[]))
+            .unwrap();
+        site.child("broken.pdf").write_str("not a PDF").unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path())
+            .arg(".")
+            .arg("--check-anchors")
+            .arg("--check-pdf-anchors");
+
+        cmd.assert().failure().code(2).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 7 links from 3 files \(1 documents\)
+\..broken\.pdf
+  warning: cannot read anchors of PDF: .+
+
+\..index\.html
+  error: bad link /broken\.pdf#page=1
+  error: bad link /manual\.pdf#outro
+  error: bad link /manual\.pdf#page=3
+
+Found 0 bad links
+Found 3 bad anchors
+Found 1 unreadable files
+$"#,
+            )
+            .unwrap(),
+        );
+
+        site.close().unwrap();
+    }
+
+    #[test]
+    fn test_text_fragments() {
+        let site = assert_fs::TempDir::new().unwrap();
+        site.child("index.html")
+            .write_str(
+                r#"<a href="guide.html#:~:text=quick%20brown,lazy%20dog"></a>
+                <a href="guide.html#usage:~:text=jumps-,over"></a>
+                <a href="guide.html#:~:text=slow%20brown"></a>
+                <a href="old.html#:~:text=jumps"></a>
+                <a href="guide.html#:~:text=Guide"></a>"#,
+            )
+            .unwrap();
+        site.child("guide.html")
+            .write_str(
+                "<title>Guide</title><h2 id=usage>The <b>quick</b> brown\n fox</h2>
+                <p>jumps over the lazy dog</p>",
+            )
+            .unwrap();
+        site.child("old.html")
+            .write_str(r#"<meta http-equiv="refresh" content="0; url=guide.html">"#)
+            .unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path()).arg(".").arg("--check-anchors");
+
+        cmd.assert().failure().code(2).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 6 links from 3 files \(3 documents\)
+Reading pages linked with text fragments
+\..index\.html
+  error: bad link /guide\.html#:~:text=Guide
+  error: bad link /guide\.html#:~:text=slow%20brown
+
+Found 0 bad links
+Found 2 bad anchors
+$"#,
+            )
+            .unwrap(),
+        );
+
+        site.close().unwrap();
+    }
+
+    #[test]
+    fn test_implicit_and_valid_anchors() {
+        let site = assert_fs::TempDir::new().unwrap();
+        site.child("index.html")
+            .write_str(
+                r##"<a href="#"></a><a href="#top"></a><a href="code.html#TOP"></a>
+                <a href="code.html#L12"></a><a href="app.html#/settings/profile"></a>
+                <a href="code.html#L12-L14"></a>"##,
+            )
+            .unwrap();
+        site.child("code.html").touch().unwrap();
+        site.child("app.html").touch().unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path())
+            .arg(".")
+            .arg("--check-anchors")
+            .arg("--valid-anchor")
+            .arg(r"#L\d+")
+            .arg("--valid-anchor")
+            .arg("/.*");
+
+        cmd.assert().failure().code(2).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 6 links from 3 files \(3 documents\)
+\..index\.html
+  error: bad link /code\.html#L12-L14
+
+Found 0 bad links
+Found 1 bad anchors
+$"#,
+            )
+            .unwrap(),
+        );
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path())
+            .arg(".")
+            .arg("--valid-anchor")
+            .arg("(");
+
+        cmd.assert().failure().code(1);
+
+        site.close().unwrap();
+    }
+
+    #[test]
+    fn test_case_mismatch() {
+        let site = assert_fs::TempDir::new().unwrap();
+        site.child("index.html")
+            .write_str(r#"<a href="Guide.html"></a><a href="guide.html#Usage"></a>"#)
+            .unwrap();
+        site.child("guide.html")
+            .write_str("<h2 id=usage>Usage</h2>")
+            .unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path()).arg(".").arg("--check-anchors");
+
+        cmd.assert().failure().code(1).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 2 links from 2 files \(2 documents\)
+\..index\.html
+  error: bad link /Guide\.html \(only differs in case from /guide\.html\)
+  error: bad link /guide\.html#Usage \(only differs in case from /guide\.html#usage\)
+
+Found 1 bad links
+Found 1 bad anchors
+$"#,
+            )
+            .unwrap(),
+        );
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path())
+            .arg(".")
+            .arg("--server-profile")
+            .arg("iis");
+
+        cmd.assert().success();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path())
+            .arg(".")
+            .arg("--server-profile")
+            .arg("iis")
+            .arg("--check-case");
+
+        cmd.assert().failure().code(2).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 2 links from 2 files \(2 documents\)
+\..index\.html
+  warning: link /Guide\.html only matches /guide\.html if case is ignored
+
+Found 0 bad links
+Found 1 links differing in case
+$"#,
+            )
+            .unwrap(),
+        );
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path()).arg(".").arg("--check-case");
+
+        cmd.assert()
+            .failure()
+            .code(1)
+            .stdout("")
+            .stderr(predicate::str::contains(
+                "Error: --check-case requires --server-profile iis",
+            ));
+
+        site.close().unwrap();
+    }
+
+    #[test]
+    fn test_unicode_normalization() {
+        let site = assert_fs::TempDir::new().unwrap();
+        site.child("index.html")
+            .write_str("<a href=\"caf\u{e9}.html#menu\"></a>")
+            .unwrap();
+        site.child("cafe\u{301}.html")
+            .write_str("<h2 id=menu>Menu</h2>")
+            .unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path()).arg(".");
+
+        cmd.assert().failure().code(1);
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path())
+            .arg(".")
+            .arg("--check-anchors")
+            .arg("--unicode-normalization")
+            .arg("nfc");
+
+        cmd.assert().failure().code(2).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 1 links from 2 files \(2 documents\)
+\..index\.html
+  warning: link /café\.html#menu only matches /cafe\\u\{301\}\.html after NFC normalization
+
+Found 0 bad links
+Found 0 bad anchors
+Found 1 links differing in Unicode normalization
+$"#,
+            )
+            .unwrap(),
+        );
+
+        site.close().unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_invalid_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let site = assert_fs::TempDir::new().unwrap();
+        site.child("index.html")
+            .write_binary(b"<a href=\"caf\xe9.html\"></a><a href=\"missing.html\"></a>")
+            .unwrap();
+        site.child(OsStr::from_bytes(b"caf\xe9.html"))
+            .write_str("")
+            .unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path()).arg(".");
+
+        cmd.assert().failure().code(1).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 1 links from 2 files \(1 documents\)
+\..caf.\.html
+  error: file name is not valid UTF-8
+
+\..index\.html
+  error: bad link /missing\.html
+  error: invalid UTF-8 at byte 12, in an attribute of <a>
+
+Found 1 bad links
+Found 2 strings with invalid UTF-8
+$"#,
+            )
+            .unwrap(),
+        );
+
+        site.close().unwrap();
+    }
+
+    #[test]
+    fn test_legacy_charset() {
+        let site = assert_fs::TempDir::new().unwrap();
+        site.child("index.html")
+            .write_binary(
+                b"<meta charset=windows-1252>\
+                  <a href=\"caf\xe9.html#men\xfc\"></a><a href=\"caf\xe9.html#carte\"></a>",
+            )
+            .unwrap();
+        site.child("caf\u{e9}.html")
+            .write_binary(
+                b"<meta http-equiv=Content-Type content=\"text/html; charset=iso-8859-1\">\
+                  <h2 id=\"men\xfc\">Men\xfc</h2>",
+            )
+            .unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path()).arg(".").arg("--check-anchors");
+
+        cmd.assert().failure().code(2).stdout(
+            predicate::str::is_match(
+                "^Reading files
+Checking 2 links from 2 files \\(2 documents\\)
+\\..index\\.html
+  error: bad link /caf\u{e9}\\.html#carte
+
+Found 0 bad links
+Found 1 bad anchors
+$",
+            )
+            .unwrap(),
+        );
+
+        site.close().unwrap();
+    }
+
+    #[test]
+    fn test_archives() {
+        let site = assert_fs::TempDir::new().unwrap();
+        let files: &[(&str, &str)] = &[
+            (
+                "index.html",
+                r#"<a href="guide/">Guide</a><a href="missing.html"></a>"#,
+            ),
+            (
+                "guide/index.html",
+                r#"<a href="../#intro"></a><a href="../#usage"></a>"#,
+            ),
+            ("robots.txt", ""),
+        ];
+
+        let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(
+            std::fs::File::create(site.child("site.tar.gz").path()).unwrap(),
+            flate2::Compression::default(),
+        ));
+        for (name, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar.append_data(&mut header, format!("./{}", name), contents.as_bytes())
+                .unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap();
+
+        let mut zip =
+            zip::ZipWriter::new(std::fs::File::create(site.child("site.zip").path()).unwrap());
+        for (name, contents) in files {
+            zip.start_file(format!("public/{}", name), Default::default())
+                .unwrap();
+            std::io::Write::write_all(&mut zip, contents.as_bytes()).unwrap();
+        }
+        zip.start_file("src/index.md", Default::default()).unwrap();
+        zip.finish().unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path())
+            .arg("site.tar.gz")
+            .arg("--check-anchors");
+
+        cmd.assert().failure().code(1).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 4 links from 3 files \(2 documents\)
+site\.tar\.gz!/guide/index\.html
+  error: bad link /#intro
+  error: bad link /#usage
+
+site\.tar\.gz!/index\.html
+  error: bad link /missing\.html
+
+Found 1 bad links
+Found 2 bad anchors
+$"#,
+            )
+            .unwrap(),
+        );
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path()).arg("site.zip!/public");
+
+        cmd.assert().failure().code(1).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 4 links from 3 files \(2 documents\)
+site\.zip!/public/index\.html
+  error: bad link /missing\.html
+
+Found 1 bad links
+$"#,
+            )
+            .unwrap(),
+        );
+
+        // Files in an archive are annotated with the path they are reported under.
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path())
+            .arg("site.zip!/public")
+            .arg("--github-actions");
+
+        cmd.assert().failure().code(1).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 4 links from 3 files \(2 documents\)
+site\.zip!/public/index\.html
+  error: bad link /missing\.html
+
+::error file=site\.zip!/public/index\.html,line=1::bad links:%0A  missing\.html
+
+Found 1 bad links
+$"#,
+            )
+            .unwrap(),
+        );
+
+        site.close().unwrap();
+    }
+
+    #[test]
+    fn test_epub() {
+        let site = assert_fs::TempDir::new().unwrap();
+        let files: &[(&str, &str)] = &[
+            ("mimetype", "application/epub+zip"),
+            (
+                "META-INF/container.xml",
+                r#"<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+                  <rootfiles>
+                    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+                  </rootfiles>
+                </container>"#,
+            ),
+            (
+                "OEBPS/content.opf",
+                r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+                  <manifest>
+                    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+                    <item id="ch1" href="text/ch1.xhtml" media-type="application/xhtml+xml"/>
+                    <item id="ch2" href="text/ch2.xhtml" media-type="application/xhtml+xml"/>
+                    <item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>
+                  </manifest>
+                  <spine toc="ncx">
+                    <itemref idref="ch1"/>
+                    <itemref idref="ch3"/>
+                  </spine>
+                </package>"#,
+            ),
+            (
+                "OEBPS/nav.xhtml",
+                r##"<nav epub:type="toc"><ol>
+                  <li><a href="text/ch1.xhtml#intro">Intro</a></li>
+                  <li><a href="text/ch1.xhtml#usage">Usage</a></li>
+                </ol></nav>"##,
+            ),
+            ("OEBPS/text/ch1.xhtml", r#"<h1 id="intro">Intro</h1>"#),
+            (
+                "OEBPS/toc.ncx",
+                r#"<ncx><navMap><navPoint id="p1">
+                  <content src="text/ch1.xhtml#setup"/>
+                </navPoint></navMap></ncx>"#,
+            ),
+        ];
+
+        let mut zip =
+            zip::ZipWriter::new(std::fs::File::create(site.child("book.epub").path()).unwrap());
+        for (name, contents) in files {
+            zip.start_file(*name, Default::default()).unwrap();
+            std::io::Write::write_all(&mut zip, contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path())
+            .arg("book.epub")
+            .arg("--check-anchors");
+
+        cmd.assert().failure().code(1).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 8 links from 6 files \(2 documents\)
+book\.epub!/OEBPS/content\.opf
+  error: bad link /OEBPS/text/ch2\.xhtml
+  error: spine refers to missing manifest item "ch3"
+
+book\.epub!/OEBPS/nav\.xhtml
+  error: bad link /OEBPS/text/ch1\.xhtml#usage
+
+book\.epub!/OEBPS/toc\.ncx
+  error: bad link /OEBPS/text/ch1\.xhtml#setup
+
+Found 1 bad links
+Found 2 bad anchors
+Found 1 EPUB package problems
+$"#,
+            )
+            .unwrap(),
+        );
+
+        let mut zip =
+            zip::ZipWriter::new(std::fs::File::create(site.child("broken.epub").path()).unwrap());
+        zip.start_file("mimetype", Default::default()).unwrap();
+        std::io::Write::write_all(&mut zip, b"application/epub+zip").unwrap();
+        zip.finish().unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path()).arg("broken.epub");
+
+        cmd.assert().failure().code(1).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 0 links from 1 files \(0 documents\)
+broken\.epub
+  error: META-INF/container\.xml is missing
+
+Found 0 bad links
+Found 1 EPUB package problems
+$"#,
+            )
+            .unwrap(),
+        );
+
+        site.close().unwrap();
+    }
+
+    #[test]
+    fn test_precompressed() {
+        use std::io::Write;
+
+        let site = assert_fs::TempDir::new().unwrap();
+
+        let mut gzip = flate2::write::GzEncoder::new(
+            std::fs::File::create(site.child("index.html.gz").path()).unwrap(),
+            flate2::Compression::default(),
+        );
+        gzip.write_all(
+            br#"<a href="guide.html#usage"></a><a href="missing.html"></a><a href="site-1.0.tar"></a>"#,
+        )
+        .unwrap();
+        gzip.finish().unwrap();
+
+        // A download is not a precompressed variant of a file that is read.
+        site.child("site-1.0.tar.gz").write_binary(b"").unwrap();
+
+        let mut brotli = brotli::CompressorWriter::new(
+            std::fs::File::create(site.child("guide.html.br").path()).unwrap(),
+            4096,
+            9,
+            22,
+        );
+        brotli
+            .write_all(br#"<h1 id="usage"></h1><a href="/"></a><a href="guide.html.br"></a>"#)
+            .unwrap();
+        drop(brotli);
+
+        // Only the original is read when both exist.
+        site.child("about.html")
+            .write_str(r#"<a href="about.html.gz"></a>"#)
+            .unwrap();
+        let mut gzip = flate2::write::GzEncoder::new(
+            std::fs::File::create(site.child("about.html.gz").path()).unwrap(),
+            flate2::Compression::default(),
+        );
+        gzip.write_all(br#"<a href="about.html.gz"></a>"#).unwrap();
+        gzip.finish().unwrap();
+
+        // Brotli is preferred over gzip when there is no original.
+        let mut gzip = flate2::write::GzEncoder::new(
+            std::fs::File::create(site.child("guide.html.gz").path()).unwrap(),
+            flate2::Compression::default(),
+        );
+        gzip.write_all(br#"<a href="stale.html"></a>"#).unwrap();
+        gzip.finish().unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path()).arg(".").arg("--check-anchors");
+
+        cmd.assert().failure().code(1).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 6 links from 6 files \(3 documents\)
+\..index\.html\.gz
+  error: bad link /missing\.html
+  error: bad link /site-1\.0\.tar
+
+Found 2 bad links
+Found 0 bad anchors
+$"#,
+            )
+            .unwrap(),
+        );
+
+        // The same holds for files in an archive, which don't exist on disk.
+        let mut page_gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        page_gz
+            .write_all(br#"<a href="missing.html"></a>"#)
+            .unwrap();
+        let page_gz = page_gz.finish().unwrap();
+
+        let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(
+            std::fs::File::create(site.child("site.tar.gz").path()).unwrap(),
+            flate2::Compression::default(),
+        ));
+        for (name, contents) in [
+            ("page.html", &br#"<a href="missing.html"></a>"#[..]),
+            ("page.html.gz", &page_gz[..]),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar.append_data(&mut header, name, contents).unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path()).arg("site.tar.gz");
+
+        cmd.assert().failure().code(1).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 1 links from 2 files \(1 documents\)
+site\.tar\.gz!/page\.html
+  error: bad link /missing\.html
+
+Found 1 bad links
+$"#,
+            )
+            .unwrap(),
+        );
+
+        site.close().unwrap();
+    }
+
+    #[test]
+    fn test_html_extensions_and_sniffing() {
+        let site = assert_fs::TempDir::new().unwrap();
+        site.child("index.html")
+            .write_str(
+                r#"<a href="about#team"></a><a href="about#jobs"></a><a href="news.shtml"></a>"#,
+            )
+            .unwrap();
+        site.child("about")
+            .write_str("\n<!DOCTYPE html>\n<h1 id=team></h1><a href=missing></a>")
+            .unwrap();
+        site.child("news.shtml")
+            .write_str(r#"<!--#include virtual="/header.html" --><a href="old-news.html"></a>"#)
+            .unwrap();
+        site.child("CNAME").write_str("example.com\n").unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path())
+            .arg(".")
+            .arg("--check-anchors")
+            .arg("--html-extension")
+            .arg(".shtml")
+            .arg("--sniff-html");
+
+        cmd.assert().failure().code(1).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 5 links from 4 files \(3 documents\)
+\..about
+  error: bad link /missing
+
+\..index\.html
+  error: bad link /about#jobs
+
+\..news\.shtml
+  error: bad link /old-news\.html
+
+Found 2 bad links
+Found 1 bad anchors
+$"#,
+            )
+            .unwrap(),
+        );
+
+        site.close().unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_follow_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let site = assert_fs::TempDir::new().unwrap();
+        site.child("index.html")
+            .write_str(r#"<a href="latest/#install"></a><a href="latest/#nope"></a>"#)
+            .unwrap();
+        site.child("v3.2/index.html")
+            .write_str(r#"<h1 id="install"></h1><img src="../assets/logo.svg"><a href="../missing.html"></a>"#)
+            .unwrap();
+        site.child("assets/logo.svg").touch().unwrap();
+        symlink("v3.2", site.child("latest").path()).unwrap();
+        symlink("..", site.child("v3.2/root").path()).unwrap();
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path())
+            .arg(".")
+            .arg("--check-anchors")
+            .arg("--follow-symlinks");
+
+        cmd.assert().failure().code(1).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 6 links from 4 files \(3 documents\)
+\./index\.html
+  error: bad link /latest#nope
+
+\./latest/index\.html
+  error: bad link /missing\.html
+
+\./latest/root
+  warning: symlink to \.\. loops back to a parent folder
+
+\./v3\.2/index\.html
+  error: bad link /missing\.html
+
+\./v3\.2/root
+  warning: symlink to \.\. loops back to a parent folder
+
+Found 2 bad links
+Found 1 bad anchors
+Found 2 symlink loops
+$"#,
+            )
+            .unwrap(),
+        );
+
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path())
+            .arg(".")
+            .arg("--check-anchors")
+            .arg("--follow-symlinks")
+            .arg("--alias-symlinks");
+
+        cmd.assert().failure().code(1).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 4 links from 4 files \(2 documents\)
+\./index\.html
+  error: bad link /latest#nope
+
+\./latest/root
+  warning: symlink to \.\. loops back to a parent folder
+
+\./v3\.2/index\.html
+  error: bad link /missing\.html
+
+\./v3\.2/root
+  warning: symlink to \.\. loops back to a parent folder
+
+Found 1 bad links
+Found 1 bad anchors
+Found 2 symlink loops
+$"#,
+            )
+            .unwrap(),
+        );
+
+        site.close().unwrap();
+    }
+
+    #[test]
+    fn test_dead_anchor() {
+        let site = assert_fs::TempDir::new().unwrap();
+        site.child("index.html")
+            .write_str("<a href=bar.html#goo>")
+            .unwrap();
+        site.child("bar.html").touch().unwrap();
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path()).arg(".").arg("--check-anchors");
+
+        cmd.assert().failure().code(2).stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 1 links from 2 files \(2 documents\)
+\..index\.html
+  error: bad link /bar.html#goo
+
+Found 0 bad links
+Found 1 bad anchors
+$"#,
+            )
+            .unwrap(),
+        );
+        site.close().unwrap();
+    }
+
+    #[test]
+    fn test_bad_base() {
+        let site = assert_fs::TempDir::new().unwrap();
+        site.child("index.html")
+            .write_str("<base href=../><a href=index.html>")
+            .unwrap();
+        let mut cmd = Command::cargo_bin("hyperlink").unwrap();
+        cmd.current_dir(site.path()).arg(".");
+
+        cmd.assert().success().stdout(
+            predicate::str::is_match(
+                r#"^Reading files
+Checking 1 links from 1 files \(1 documents\)
+\..index\.html
+  warning: <base href="\.\./"> points outside of the site, ignoring it
+
+Found 0 bad links
+Found 1 bad base hrefs
 $"#,
             )
             .unwrap(),
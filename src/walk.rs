@@ -0,0 +1,290 @@
+//! Finding the files of a site on disk, optionally following symlinks, see `--follow-symlinks`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Error;
+use jwalk::WalkDir;
+use rayon::prelude::*;
+
+use crate::html::CheckOptions;
+
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+/// How `walk_files` treats symlinks.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Symlinks {
+    /// Skip symlinks as if they didn't exist.
+    Skip,
+    /// Walk symlinked files and folders like any other, see `--follow-symlinks`.
+    Follow,
+    /// Like `Follow`, and point out symlinked files whose target was walked already, see
+    /// `--alias-symlinks`.
+    Alias,
+}
+
+impl Symlinks {
+    pub fn from_options(options: &CheckOptions) -> Symlinks {
+        match (options.follow_symlinks, options.alias_symlinks) {
+            (false, _) => Symlinks::Skip,
+            (true, false) => Symlinks::Follow,
+            (true, true) => Symlinks::Alias,
+        }
+    }
+}
+
+/// A file found by `walk_files`.
+pub struct WalkedFile {
+    pub path: PathBuf,
+    /// The file already walked under another path that this file is a symlink to, with
+    /// `Symlinks::Alias`.
+    pub alias_of: Option<PathBuf>,
+}
+
+pub struct Walk {
+    /// Files sorted by path, except that files in symlinked folders come after all others.
+    pub files: Vec<WalkedFile>,
+    /// Symlinks that were not followed because they point to one of their own parent folders.
+    pub loops: Vec<PathBuf>,
+}
+
+impl Walk {
+    pub fn paths(&self) -> BTreeSet<PathBuf> {
+        self.files.iter().map(|file| file.path.clone()).collect()
+    }
+
+    pub fn into_par_iter(self) -> impl ParallelIterator<Item = WalkedFile> {
+        // Minimize amount of LinkCollector instances created. This impacts parallelism but
+        // `LinkCollector::merge` is rather slow.
+        let min_len = self.files.len() / rayon::current_num_threads();
+        self.files.into_par_iter().with_min_len(min_len)
+    }
+}
+
+pub fn walk_files(base_path: &Path, symlinks: Symlinks) -> Result<Walk, Error> {
+    let mut walk = Walk {
+        files: Vec::new(),
+        loops: Vec::new(),
+    };
+
+    let mut symlinked = Vec::new();
+    walk_dir(base_path, symlinks, &mut walk.files, &mut symlinked)?;
+
+    let real_files_len = walk.files.len();
+
+    // Folders are followed breadth-first. Each one remembers the real folders it was reached
+    // through, so that symlinks back into any of them are detected as loops.
+    let root = vec![fs::canonicalize(base_path)?];
+    let mut queue: Vec<_> = symlinked
+        .into_iter()
+        .map(|path| (path, root.clone()))
+        .collect();
+
+    while !queue.is_empty() {
+        let mut next_queue = Vec::new();
+
+        for (path, ancestors) in queue {
+            let target = match fs::metadata(&path) {
+                Ok(metadata) if metadata.is_dir() => fs::canonicalize(&path)?,
+                Ok(_) => {
+                    walk.files.push(WalkedFile {
+                        path,
+                        alias_of: None,
+                    });
+                    continue;
+                }
+                // Broken symlinks are treated like missing files.
+                Err(_) => continue,
+            };
+
+            let parent = fs::canonicalize(path.parent().unwrap_or(base_path))?;
+
+            if ancestors
+                .iter()
+                .chain(Some(&parent))
+                .any(|ancestor| ancestor.starts_with(&target))
+            {
+                walk.loops.push(path);
+                continue;
+            }
+
+            let mut ancestors = ancestors;
+            ancestors.push(target);
+
+            let mut symlinked = Vec::new();
+            walk_dir(&path, symlinks, &mut walk.files, &mut symlinked)?;
+            next_queue.extend(symlinked.into_iter().map(|path| (path, ancestors.clone())));
+        }
+
+        queue = next_queue;
+    }
+
+    if symlinks == Symlinks::Alias {
+        find_aliases(base_path, &mut walk.files, real_files_len)?;
+    }
+
+    Ok(walk)
+}
+
+/// Collect the files in `path` into `files`, and the symlinks into `symlinked` if they are
+/// followed.
+fn walk_dir(
+    path: &Path,
+    symlinks: Symlinks,
+    files: &mut Vec<WalkedFile>,
+    symlinked: &mut Vec<PathBuf>,
+) -> Result<(), Error> {
+    let entries = WalkDir::new(path)
+        .sort(true) // helps branch predictor (?)
+        .process_read_dir(move |_, _, _, children| {
+            children.retain(|dir_entry_result| {
+                let entry = match dir_entry_result.as_ref() {
+                    Ok(x) => x,
+                    Err(_) => return true,
+                };
+
+                let file_type = entry.file_type();
+
+                // need to retain directories and the root, otherwise jwalk won't recurse
+                if file_type.is_dir() || entry.depth() == 0 {
+                    return true;
+                }
+                if file_type.is_symlink() {
+                    return symlinks != Symlinks::Skip;
+                }
+
+                if !file_type.is_file() {
+                    return false;
+                }
+
+                true
+            });
+        })
+        .into_iter()
+        .filter_map(|entry| {
+            let entry = match entry {
+                Ok(x) => x,
+                Err(e) => return Some(Err(e)),
+            };
+
+            if entry.file_type().is_dir() || entry.depth() == 0 {
+                None
+            } else {
+                Some(Ok(entry))
+            }
+        })
+        // XXX: cannot use par_bridge because of https://github.com/rayon-rs/rayon/issues/690
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for entry in entries {
+        if entry.file_type().is_symlink() {
+            symlinked.push(entry.path());
+        } else {
+            files.push(WalkedFile {
+                path: entry.path(),
+                alias_of: None,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Point every file reached through a symlink at the first file walked with the same target.
+/// `files[..real_files_len]` were not reached through a symlink.
+fn find_aliases(
+    base_path: &Path,
+    files: &mut [WalkedFile],
+    real_files_len: usize,
+) -> Result<(), Error> {
+    let canonical_base_path = fs::canonicalize(base_path)?;
+    let real_files: BTreeSet<_> = files[..real_files_len]
+        .iter()
+        .map(|file| file.path.clone())
+        .collect();
+    let mut targets = BTreeMap::new();
+
+    for file in &mut files[real_files_len..] {
+        let target = fs::canonicalize(&file.path)?;
+
+        let real_file = target
+            .strip_prefix(&canonical_base_path)
+            .ok()
+            .map(|path| base_path.join(path))
+            .filter(|path| real_files.contains(path));
+
+        file.alias_of = match real_file {
+            Some(real_file) => Some(real_file),
+            None => targets.get(&target).cloned(),
+        };
+
+        if file.alias_of.is_none() {
+            targets.insert(target, file.path.clone());
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn test_walk_files() {
+    use std::os::unix::fs::symlink;
+
+    let site = assert_fs::TempDir::new().unwrap();
+    let base_path = site.path();
+
+    fs::create_dir_all(base_path.join("v3.2/assets")).unwrap();
+    fs::write(base_path.join("v3.2/index.html"), "").unwrap();
+    fs::write(base_path.join("v3.2/assets/logo.svg"), "").unwrap();
+    symlink("v3.2", base_path.join("latest")).unwrap();
+    symlink("..", base_path.join("v3.2/root")).unwrap();
+    symlink("missing.html", base_path.join("broken.html")).unwrap();
+
+    let paths = |walk: &Walk| -> Vec<_> {
+        walk.files
+            .iter()
+            .map(|file| {
+                let path = file.path.strip_prefix(base_path).unwrap().to_owned();
+                let alias_of = file
+                    .alias_of
+                    .as_ref()
+                    .map(|alias_of| alias_of.strip_prefix(base_path).unwrap().to_owned());
+                (path, alias_of)
+            })
+            .collect()
+    };
+
+    let walk = walk_files(base_path, Symlinks::Skip).unwrap();
+    assert_eq!(
+        paths(&walk),
+        &[
+            (PathBuf::from("v3.2/assets/logo.svg"), None),
+            (PathBuf::from("v3.2/index.html"), None),
+        ]
+    );
+    assert!(walk.loops.is_empty());
+
+    let walk = walk_files(base_path, Symlinks::Alias).unwrap();
+    assert_eq!(
+        paths(&walk),
+        &[
+            (PathBuf::from("v3.2/assets/logo.svg"), None),
+            (PathBuf::from("v3.2/index.html"), None),
+            (
+                PathBuf::from("latest/assets/logo.svg"),
+                Some(PathBuf::from("v3.2/assets/logo.svg"))
+            ),
+            (
+                PathBuf::from("latest/index.html"),
+                Some(PathBuf::from("v3.2/index.html"))
+            ),
+        ]
+    );
+    assert_eq!(
+        walk.loops,
+        &[base_path.join("v3.2/root"), base_path.join("latest/root")]
+    );
+}